@@ -0,0 +1,90 @@
+use crate::line;
+
+// Length in bytes of the OSC escape sequence (e.g. a hyperlink) at the start of the given text, or
+// 0 if it does not begin with a complete one
+pub(crate) fn osc_sequence_len(text: &str) -> usize {
+  let bytes = text.as_bytes();
+  if bytes.len() < 3 || bytes[0] != b'\x1b' || bytes[1] != b']' {
+    return 0;
+  }
+
+  for i in 2..bytes.len() {
+    if bytes[i] == b'\x07' {
+      return i + 1;
+    } else if bytes[i] == b'\x1b' && bytes.get(i + 1) == Some(&b'\\') {
+      return i + 2;
+    }
+  }
+  0
+}
+
+// A choice split into the text that is displayed and matched against and the escape sequences
+// that were found within it
+pub(crate) struct AnsiString {
+  pub text: String,
+  // (offset within text that the escape sequence preceded, start byte, end byte) where the start
+  // and end bytes refer to the original string
+  pub escapes: Vec<(usize, usize, usize)>,
+}
+
+impl AnsiString {
+  pub fn new(raw: &str) -> AnsiString {
+    let mut text = String::with_capacity(raw.len());
+    let mut escapes = Vec::new();
+
+    let mut offset = 0;
+    while offset < raw.len() {
+      let rest = &raw[offset..];
+      let escape_len = std::cmp::max(line::escape_sequence_len(rest), osc_sequence_len(rest));
+      if escape_len != 0 {
+        escapes.push((text.len(), offset, offset + escape_len));
+        offset += escape_len;
+      } else {
+        let c = rest.chars().next().unwrap();
+        text.push(c);
+        offset += c.len_utf8();
+      }
+    }
+
+    AnsiString { text, escapes }
+  }
+}
+
+/// Remove ANSI escape sequences from the text
+pub fn strip_ansi(text: &str) -> String {
+  AnsiString::new(text).text
+}
+
+// Tracks the graphic rendition (colours, bold etc.) that is active at a point in a line so that it
+// can be restored after drawing a highlight
+pub(crate) struct SgrState {
+  sequences: String,
+}
+
+impl SgrState {
+  pub fn new() -> SgrState {
+    SgrState {
+      sequences: String::new(),
+    }
+  }
+
+  // Returns false when the escape sequence is not an SGR sequence
+  pub fn apply(&mut self, sequence: &str) -> bool {
+    if !sequence.starts_with("\x1b[") || !sequence.ends_with('m') {
+      return false;
+    }
+
+    let params = &sequence[2..sequence.len() - 1];
+    if params.is_empty() || params == "0" {
+      self.sequences.clear();
+    } else {
+      self.sequences.push_str(sequence);
+    }
+    true
+  }
+
+  // The sequences needed to restore the state after an SGR reset
+  pub fn as_str(&self) -> &str {
+    &self.sequences
+  }
+}
@@ -0,0 +1,73 @@
+use crate::{
+  color,
+  config::{self, Config, Dedup},
+  other_error,
+};
+use std::io;
+
+// Apply command line arguments on top of the options loaded from the configuration file
+pub(crate) fn apply_args<I>(conf: &mut Config, args: I) -> io::Result<()>
+where
+  I: IntoIterator<Item = String>,
+{
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    // support both "--option=value" and "--option value"
+    let (name, inline_value) = match arg.find('=') {
+      Some(idx) if arg.starts_with("--") => (&arg[0..idx], Some(arg[idx + 1..].to_string())),
+      _ => (&arg[..], None),
+    };
+
+    macro_rules! value {
+      () => {
+        match inline_value.clone().or_else(|| args.next()) {
+          Some(value) => value,
+          None => return other_error!("Option {} requires a value", name),
+        }
+      };
+    }
+
+    match name {
+      "--ansi" => conf.ansi = true,
+      "--color" => color::parse_color_list(&value!(), &mut conf.colors)?,
+      "--no-color" => conf.no_color = true,
+      "--header" => conf.header = Some(value!()),
+      "--header-lines" => conf.header_lines = parse_number(name, &value!())?,
+      "--tiebreak" => conf.tiebreak = config::parse_tiebreak_list(&value!())?,
+      "--no-sort" => conf.no_sort = true,
+      "--tac" => conf.tac = true,
+      // the value is optional so it is only taken from "--dedup=value"
+      "--dedup" => {
+        conf.dedup = match inline_value.as_deref() {
+          None | Some("first") => Some(Dedup::First),
+          Some("last") => Some(Dedup::Last),
+          Some(value) => return other_error!("Option --dedup expects first or last: {}", value),
+        }
+      }
+      "--read0" => conf.read0 = true,
+      "--print0" => conf.print0 = true,
+      "--no-trim" => conf.no_trim = true,
+      "--expect" => conf.expect = config::parse_key_list(&value!())?,
+      "--print-query" => conf.print_query = true,
+      "--history" => {
+        conf.history.enabled = true;
+        conf.history.file = Some(value!().into());
+      }
+      "--history-context" => {
+        conf.history.enabled = true;
+        conf.history.context = value!();
+      }
+      "--history-size" => conf.history.max_size = parse_number(name, &value!())?,
+      _ => return other_error!("Unknown option: {}", arg),
+    }
+  }
+
+  Ok(())
+}
+
+fn parse_number(name: &str, value: &str) -> io::Result<usize> {
+  match value.parse() {
+    Ok(number) => Ok(number),
+    Err(_) => other_error!("Option {} requires a number: {}", name, value),
+  }
+}
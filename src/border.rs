@@ -0,0 +1,178 @@
+use crate::{
+  config::{BorderStyle, Spacing, WindowConfig},
+  terminal::Terminal,
+};
+use std::io;
+
+pub(crate) struct BorderChars {
+  top_left: &'static str,
+  top_right: &'static str,
+  bottom_left: &'static str,
+  bottom_right: &'static str,
+  horizontal: &'static str,
+  vertical: &'static str,
+}
+
+const ROUNDED: BorderChars = BorderChars {
+  top_left: "╭",
+  top_right: "╮",
+  bottom_left: "╰",
+  bottom_right: "╯",
+  horizontal: "─",
+  vertical: "│",
+};
+
+const SHARP: BorderChars = BorderChars {
+  top_left: "┌",
+  top_right: "┐",
+  bottom_left: "└",
+  bottom_right: "┘",
+  horizontal: "─",
+  vertical: "│",
+};
+
+const ASCII: BorderChars = BorderChars {
+  top_left: "+",
+  top_right: "+",
+  bottom_left: "+",
+  bottom_right: "+",
+  horizontal: "-",
+  vertical: "|",
+};
+
+// Whether the locale uses UTF-8, following the precedence of the locale environment variables
+pub(crate) fn is_utf8_locale() -> bool {
+  ["LC_ALL", "LC_CTYPE", "LANG"]
+    .iter()
+    .filter_map(|name| std::env::var(name).ok())
+    .find(|value| !value.is_empty())
+    .is_some_and(|value| {
+      let value = value.to_lowercase();
+      value.contains("utf-8") || value.contains("utf8")
+    })
+}
+
+// The space around the contents of the window, made up of the margin outside of the optional
+// border and the padding inside of it
+pub(crate) struct Frame {
+  margin: Spacing,
+  padding: Spacing,
+  border: Option<&'static BorderChars>,
+}
+
+impl Frame {
+  pub fn new(window: &WindowConfig) -> Frame {
+    let border = match window.border {
+      BorderStyle::None => None,
+      _ if !is_utf8_locale() => Some(&ASCII),
+      BorderStyle::Rounded => Some(&ROUNDED),
+      BorderStyle::Sharp => Some(&SHARP),
+    };
+
+    Frame {
+      margin: window.margin,
+      padding: window.padding,
+      border,
+    }
+  }
+
+  fn border_size(&self) -> usize {
+    if self.border.is_some() {
+      1
+    } else {
+      0
+    }
+  }
+
+  // number of rows above the contents
+  pub fn top(&self) -> usize {
+    self.margin.top + self.border_size() + self.padding.top
+  }
+
+  // number of rows below the contents
+  pub fn bottom(&self) -> usize {
+    self.margin.bottom + self.border_size() + self.padding.bottom
+  }
+
+  // number of columns to the left of the contents
+  pub fn left(&self) -> usize {
+    self.margin.left + self.border_size() + self.padding.left
+  }
+
+  // number of columns to the right of the contents
+  pub fn right(&self) -> usize {
+    self.margin.right + self.border_size() + self.padding.right
+  }
+
+  fn draw_border_line(
+    &self,
+    terminal: &mut dyn Terminal,
+    width: usize,
+    left: &str,
+    right: &str,
+  ) -> io::Result<()> {
+    if let Some(border) = self.border {
+      terminal.print(&" ".repeat(self.margin.left))?;
+      terminal.print(left)?;
+      let inner_width = width.saturating_sub(self.margin.left + self.margin.right + 2);
+      terminal.print(&border.horizontal.repeat(inner_width))?;
+      terminal.print(right)?;
+    }
+    Ok(())
+  }
+
+  // Draw the rows above the contents, the cursor is left at the start of the first row of the
+  // contents
+  pub fn draw_top(&self, terminal: &mut dyn Terminal, width: usize) -> io::Result<()> {
+    for _ in 0..self.margin.top {
+      terminal.newline()?;
+    }
+    if let Some(border) = self.border {
+      self.draw_border_line(terminal, width, border.top_left, border.top_right)?;
+      terminal.newline()?;
+    }
+    for _ in 0..self.padding.top {
+      self.draw_row_start(terminal)?;
+      self.draw_row_end(terminal, width)?;
+      terminal.newline()?;
+    }
+    Ok(())
+  }
+
+  // Draw the rows below the contents starting from the end of the last row of the contents
+  pub fn draw_bottom(&self, terminal: &mut dyn Terminal, width: usize) -> io::Result<()> {
+    for _ in 0..self.padding.bottom {
+      terminal.newline()?;
+      self.draw_row_start(terminal)?;
+      self.draw_row_end(terminal, width)?;
+    }
+    if let Some(border) = self.border {
+      terminal.newline()?;
+      self.draw_border_line(terminal, width, border.bottom_left, border.bottom_right)?;
+    }
+    for _ in 0..self.margin.bottom {
+      terminal.newline()?;
+    }
+    Ok(())
+  }
+
+  // draw the left side of a row of the contents
+  pub fn draw_row_start(&self, terminal: &mut dyn Terminal) -> io::Result<()> {
+    terminal.print(&" ".repeat(self.margin.left))?;
+    if let Some(border) = self.border {
+      terminal.print(border.vertical)?;
+    }
+    terminal.print(&" ".repeat(self.padding.left))
+  }
+
+  // clear the rest of a row of the contents and draw the right side of it
+  pub fn draw_row_end(&self, terminal: &mut dyn Terminal, width: usize) -> io::Result<()> {
+    terminal.clearline()?;
+    if let Some(border) = self.border {
+      let border_col = width.saturating_sub(self.margin.right + 1);
+      terminal.set_col(border_col)?;
+      terminal.print(border.vertical)?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,296 @@
+use crate::{
+  ansi::strip_ansi,
+  args,
+  config::{load_config, Config, Dedup},
+  Naru,
+};
+use std::{
+  collections::HashSet,
+  io,
+  io::{BufRead, ErrorKind, Write},
+};
+
+// A line of input. The text is used for matching and display, the original bytes are kept when
+// they are not valid UTF-8 so that they can be output unchanged.
+struct Line {
+  text: String,
+  raw: Option<Box<[u8]>>,
+}
+
+impl Line {
+  fn new(bytes: &[u8], trim: bool) -> Line {
+    // trimmed before decoding so that lines are trimmed the same way whether or not they are valid
+    // UTF-8
+    let bytes = if trim { trim_whitespace(bytes) } else { bytes };
+    match std::str::from_utf8(bytes) {
+      Ok(text) => Line {
+        text: text.to_string(),
+        raw: None,
+      },
+      Err(_) => Line {
+        text: String::from_utf8_lossy(bytes).into_owned(),
+        raw: Some(bytes.into()),
+      },
+    }
+  }
+
+  fn bytes(&self) -> &[u8] {
+    self.raw.as_deref().unwrap_or(self.text.as_bytes())
+  }
+}
+
+// Remove the leading and trailing ASCII whitespace
+fn trim_whitespace(bytes: &[u8]) -> &[u8] {
+  let start = bytes
+    .iter()
+    .position(|byte| !byte.is_ascii_whitespace())
+    .unwrap_or(bytes.len());
+  let end = bytes
+    .iter()
+    .rposition(|byte| !byte.is_ascii_whitespace())
+    .map_or(start, |last| last + 1);
+  &bytes[start..end]
+}
+
+// Remove duplicate choices keeping either the first or last occurrence of each one in the input
+fn dedup_choices(choices: &mut Vec<Line>, keep: Dedup) {
+  let mut seen: HashSet<&[u8]> = HashSet::with_capacity(choices.len());
+  let is_kept: Vec<bool> = match keep {
+    Dedup::First => choices
+      .iter()
+      .map(|choice| seen.insert(choice.bytes()))
+      .collect(),
+    Dedup::Last => {
+      let mut is_kept: Vec<bool> = choices
+        .iter()
+        .rev()
+        .map(|choice| seen.insert(choice.bytes()))
+        .collect();
+      is_kept.reverse();
+      is_kept
+    }
+  };
+
+  let mut is_kept = is_kept.into_iter();
+  choices.retain(|_| is_kept.next().unwrap_or(false));
+}
+
+// Remove duplicates then reverse the choices as configured, duplicates are removed first so that
+// the occurrence that is kept refers to the order of the input
+fn order_choices(choices: &mut Vec<Line>, conf: &Config) {
+  if let Some(keep) = conf.dedup {
+    dedup_choices(choices, keep);
+  }
+  if conf.tac {
+    choices.reverse();
+  }
+}
+
+// Write a line of output followed by the configured terminator
+fn write_line<W: Write>(out: &mut W, bytes: &[u8], conf: &Config) -> io::Result<()> {
+  out.write_all(bytes)?;
+  out.write_all(if conf.print0 { b"\0" } else { b"\n" })
+}
+
+// Write a selected choice as it was read, in ansi mode the escape sequences are removed from the
+// parts that are valid UTF-8
+fn write_choice<W: Write>(out: &mut W, bytes: &[u8], conf: &Config) -> io::Result<()> {
+  if conf.ansi {
+    let mut stripped = Vec::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+      stripped.extend_from_slice(strip_ansi(chunk.valid()).as_bytes());
+      stripped.extend_from_slice(chunk.invalid());
+    }
+    write_line(out, &stripped, conf)
+  } else {
+    write_line(out, bytes, conf)
+  }
+}
+
+// Read the lines of input separated by the configured delimiter, empty lines are skipped
+fn read_lines<R: BufRead>(mut reader: R, conf: &Config) -> io::Result<Vec<Line>> {
+  let delimiter = if conf.read0 { b'\0' } else { b'\n' };
+  let mut lines: Vec<Line> = Vec::new();
+  let mut input: Vec<u8> = Vec::new();
+  loop {
+    let n = reader.read_until(delimiter, &mut input)?;
+    if n == 0 {
+      break;
+    }
+    if input.last() == Some(&delimiter) {
+      input.pop();
+    }
+    let line = Line::new(&input, !conf.no_trim);
+    if !line.text.is_empty() {
+      lines.push(line);
+    }
+    input.clear();
+  }
+  Ok(lines)
+}
+
+fn match_input(conf: &Config) -> io::Result<()> {
+  let stdin = io::stdin();
+  let mut lines = read_lines(stdin.lock(), conf)?;
+
+  let header_count = std::cmp::min(conf.header_lines, lines.len());
+  let mut choices = lines.split_off(header_count);
+  let header_lines: Vec<String> = lines.into_iter().map(|line| line.text).collect();
+
+  order_choices(&mut choices, conf);
+  let (choices, raw_choices): (Vec<String>, Vec<_>) = choices
+    .into_iter()
+    .map(|line| (line.text, line.raw))
+    .unzip();
+
+  let naru = Naru::new(choices)
+    .header_lines(header_lines)
+    .config(conf.clone());
+  let selection = naru.run()?;
+  let choices = naru.choices();
+
+  let stdout = io::stdout();
+  let mut stdout = stdout.lock();
+  if conf.print_query {
+    write_line(&mut stdout, selection.query.as_bytes(), conf)?;
+  }
+  if !conf.expect.is_empty() {
+    // the key is output even when enter was pressed so that the selection is always on the lines
+    // that follow
+    write_line(
+      &mut stdout,
+      selection.key.as_deref().unwrap_or("").as_bytes(),
+      conf,
+    )?;
+  }
+  if selection.query_accepted {
+    write_line(&mut stdout, selection.query.as_bytes(), conf)?;
+  }
+  for &idx in &selection.indexes {
+    let bytes = raw_choices[idx]
+      .as_deref()
+      .unwrap_or(choices[idx].as_bytes());
+    write_choice(&mut stdout, bytes, conf)?;
+  }
+  Ok(())
+}
+
+/// Run the naru command with the arguments of the process
+pub fn main() -> io::Result<()> {
+  let result = load_config().and_then(|mut conf| {
+    // https://no-color.org
+    if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+      conf.no_color = true;
+    }
+    conf.multi = true;
+    args::apply_args(&mut conf, std::env::args().skip(1))?;
+    match_input(&conf)
+  });
+
+  match result {
+    Err(ref e) if e.kind() == ErrorKind::Other => {
+      eprintln!("{}", e);
+      Ok(())
+    }
+    default => default,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn lines(texts: &[&str]) -> Vec<Line> {
+    texts
+      .iter()
+      .map(|text| Line::new(text.as_bytes(), true))
+      .collect()
+  }
+
+  fn texts(lines: &[Line]) -> Vec<&str> {
+    lines.iter().map(|line| line.text.as_str()).collect()
+  }
+
+  #[test]
+  fn keeps_the_first_or_last_of_each_duplicate() {
+    let mut choices = lines(&["a", "b", "a", "c", "b"]);
+    dedup_choices(&mut choices, Dedup::First);
+    assert_eq!(texts(&choices), ["a", "b", "c"]);
+
+    let mut choices = lines(&["a", "b", "a", "c", "b"]);
+    dedup_choices(&mut choices, Dedup::Last);
+    assert_eq!(texts(&choices), ["a", "c", "b"]);
+  }
+
+  #[test]
+  fn lists_each_entry_once_with_the_newest_first_with_tac_and_dedup_last() {
+    // shell history with the oldest entry first
+    let mut choices = lines(&["ls", "make", "ls", "git status", "make"]);
+    let conf = Config {
+      tac: true,
+      dedup: Some(Dedup::Last),
+      ..Config::default()
+    };
+    order_choices(&mut choices, &conf);
+    assert_eq!(texts(&choices), ["make", "git status", "ls"]);
+  }
+
+  #[test]
+  fn reads_and_writes_choices_separated_by_null_characters() -> io::Result<()> {
+    let conf = Config {
+      read0: true,
+      print0: true,
+      ..Config::default()
+    };
+    let input: &[u8] = b"one\ntwo\0three\0";
+    let lines = read_lines(input, &conf)?;
+    assert_eq!(texts(&lines), ["one\ntwo", "three"]);
+
+    let mut out = Vec::new();
+    for line in &lines {
+      write_choice(&mut out, line.bytes(), &conf)?;
+    }
+    assert_eq!(out, b"one\ntwo\0three\0");
+    Ok(())
+  }
+
+  #[test]
+  fn writes_choices_without_their_escape_sequences_in_ansi_mode() -> io::Result<()> {
+    let conf = Config {
+      ansi: true,
+      ..Config::default()
+    };
+    let mut out = Vec::new();
+    write_choice(&mut out, b"\x1b[31mred\x1b[0m \xff", &conf)?;
+    assert_eq!(out, b"red \xff\n");
+    Ok(())
+  }
+
+  #[test]
+  fn trims_ascii_whitespace_only() {
+    assert_eq!(trim_whitespace(b" \t a b \r\n"), b"a b");
+    assert_eq!(trim_whitespace(b"  "), b"");
+    // a no-break space isn't ASCII whitespace
+    assert_eq!(
+      Line::new(" \u{a0}a\u{a0} ".as_bytes(), true).text,
+      "\u{a0}a\u{a0}"
+    );
+  }
+
+  #[test]
+  fn keeps_whitespace_when_not_trimming() {
+    let line = Line::new(b" a\t", false);
+    assert_eq!(line.text, " a\t");
+    assert_eq!(line.bytes(), b" a\t");
+  }
+
+  #[test]
+  fn trims_invalid_utf8_like_valid_utf8() {
+    let line = Line::new(b" \xffa ", true);
+    assert_eq!(line.text, "\u{fffd}a");
+    assert_eq!(line.bytes(), b"\xffa");
+
+    let line = Line::new(b" \xffa ", false);
+    assert_eq!(line.bytes(), b" \xffa ");
+  }
+}
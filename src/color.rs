@@ -0,0 +1,285 @@
+use crate::other_error;
+use serde::{de, Deserialize, Deserializer};
+use std::{collections::HashMap, io, str::FromStr};
+
+// The parts of the interface that can be coloured via the [colors] configuration section
+pub(crate) const COLOR_KEYS: &[&str] = &[
+  "match",
+  "selected-bg",
+  "selected-fg",
+  "prompt",
+  "info",
+  "marker",
+  "pointer",
+  "header",
+];
+
+const COLOR_NAMES: &[&str] = &[
+  "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// A colour for the foreground or background of text
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+  /// The colour the terminal uses when none is set
+  Default,
+  /// One of the 16 standard colours, 8 to 15 are the bright variants
+  Ansi(u8),
+  /// An index into the 256 colour palette
+  Indexed(u8),
+  /// A 24-bit colour
+  Rgb(u8, u8, u8),
+}
+
+// The colours a terminal can show, from fewest to most
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub(crate) enum ColorDepth {
+  Ansi8,
+  Ansi16,
+  Indexed256,
+  TrueColor,
+}
+
+impl ColorDepth {
+  // Terminals with 24-bit colour set COLORTERM to truecolor or 24bit, otherwise the depth is the
+  // colors number of the terminfo entry. Entries without it are assumed to have the 8 colours
+  // every colour terminal has.
+  pub fn new(colorterm: Option<&str>, max_colors: Option<i32>) -> ColorDepth {
+    if matches!(colorterm, Some("truecolor") | Some("24bit")) {
+      return ColorDepth::TrueColor;
+    }
+    match max_colors {
+      Some(colors) if colors >= 1 << 24 => ColorDepth::TrueColor,
+      Some(colors) if colors >= 256 => ColorDepth::Indexed256,
+      Some(colors) if colors >= 16 => ColorDepth::Ansi16,
+      _ => ColorDepth::Ansi8,
+    }
+  }
+}
+
+// the default xterm values of the 16 standard colours
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+  (0, 0, 0),
+  (205, 0, 0),
+  (0, 205, 0),
+  (205, 205, 0),
+  (0, 0, 238),
+  (205, 0, 205),
+  (0, 205, 205),
+  (229, 229, 229),
+  (127, 127, 127),
+  (255, 0, 0),
+  (0, 255, 0),
+  (255, 255, 0),
+  (92, 92, 255),
+  (255, 0, 255),
+  (0, 255, 255),
+  (255, 255, 255),
+];
+
+// the levels of each component in the 6x6x6 colour cube at indexes 16 to 231 of the palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Color {
+  // The nearest colour that a terminal with the depth can show
+  pub(crate) fn downgrade(self, depth: ColorDepth) -> Color {
+    match (self, depth) {
+      (_, ColorDepth::TrueColor) | (Color::Default, _) => self,
+      (Color::Indexed(_), ColorDepth::Indexed256) | (Color::Ansi(_), ColorDepth::Indexed256) => {
+        self
+      }
+      (Color::Rgb(r, g, b), ColorDepth::Indexed256) => Color::Indexed(nearest_indexed(r, g, b)),
+      (Color::Ansi(idx), ColorDepth::Ansi16) => Color::Ansi(idx),
+      // the bright variants are shown as the normal colours
+      (Color::Ansi(idx), ColorDepth::Ansi8) => Color::Ansi(idx % 8),
+      (Color::Indexed(idx), _) if idx < 16 => Color::Ansi(idx).downgrade(depth),
+      (Color::Indexed(idx), _) => {
+        let (r, g, b) = indexed_rgb(idx);
+        Color::Rgb(r, g, b).downgrade(depth)
+      }
+      (Color::Rgb(r, g, b), _) => {
+        let count = if depth == ColorDepth::Ansi16 { 16 } else { 8 };
+        let idx = (0..count)
+          .min_by_key(|&idx| distance((r, g, b), ANSI_RGB[idx]))
+          .unwrap_or(0);
+        Color::Ansi(idx as u8)
+      }
+    }
+  }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+  let component = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+  component(r1, r2) + component(g1, g2) + component(b1, b2)
+}
+
+// The nearest colour of the colour cube or the grey ramp. The standard colours aren't used since
+// terminals change them with their themes.
+fn nearest_indexed(r: u8, g: u8, b: u8) -> u8 {
+  let level = |component: u8| {
+    (0..CUBE_LEVELS.len())
+      .min_by_key(|&idx| (i32::from(CUBE_LEVELS[idx]) - i32::from(component)).abs())
+      .unwrap_or(0) as u8
+  };
+  let cube_idx = 16 + 36 * level(r) + 6 * level(g) + level(b);
+
+  // the grey ramp runs from 8 to 238 in steps of 10
+  let average = (u32::from(r) + u32::from(g) + u32::from(b)) / 3;
+  let grey_idx = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+
+  if distance((r, g, b), indexed_rgb(grey_idx)) < distance((r, g, b), indexed_rgb(cube_idx)) {
+    grey_idx
+  } else {
+    cube_idx
+  }
+}
+
+fn indexed_rgb(idx: u8) -> (u8, u8, u8) {
+  match idx {
+    0..=15 => ANSI_RGB[idx as usize],
+    16..=231 => {
+      let idx = idx - 16;
+      (
+        CUBE_LEVELS[(idx / 36) as usize],
+        CUBE_LEVELS[(idx / 6 % 6) as usize],
+        CUBE_LEVELS[(idx % 6) as usize],
+      )
+    }
+    _ => {
+      let level = 8 + 10 * (idx - 232);
+      (level, level, level)
+    }
+  }
+}
+
+impl FromStr for Color {
+  type Err = io::Error;
+
+  // Parse names like "red" or "bright-red", 256 colour palette indexes and "#rrggbb"
+  fn from_str(value: &str) -> io::Result<Color> {
+    if value == "default" {
+      return Ok(Color::Default);
+    }
+
+    if let Some(hex) = value.strip_prefix('#') {
+      if hex.len() == 6 {
+        if let Ok(rgb) = u32::from_str_radix(hex, 16) {
+          return Ok(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+        }
+      }
+      return other_error!("Invalid colour, expected #rrggbb: {}", value);
+    }
+
+    if let Ok(idx) = value.parse::<u8>() {
+      return Ok(Color::Indexed(idx));
+    }
+
+    let (name, bright) = match value.strip_prefix("bright-") {
+      Some(name) => (name, true),
+      None => (value, false),
+    };
+    match COLOR_NAMES
+      .iter()
+      .position(|&color_name| color_name == name)
+    {
+      Some(idx) => Ok(Color::Ansi(idx as u8 + if bright { 8 } else { 0 })),
+      None => other_error!("Invalid colour: {}", value),
+    }
+  }
+}
+
+// palette indexes may be written as numbers in the configuration file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorValue {
+  Index(u8),
+  Name(String),
+}
+
+impl<'de> Deserialize<'de> for Color {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    match ColorValue::deserialize(deserializer)? {
+      ColorValue::Index(idx) => Ok(Color::Indexed(idx)),
+      ColorValue::Name(name) => name.parse().map_err(de::Error::custom),
+    }
+  }
+}
+
+pub(crate) fn validate_colors(colors: &HashMap<String, Color>) -> io::Result<()> {
+  for key in colors.keys() {
+    if !COLOR_KEYS.contains(&key.as_str()) {
+      return other_error!("Invalid colour name '{}'", key);
+    }
+  }
+  Ok(())
+}
+
+// Parse a comma separated list of key=colour pairs e.g. "match=red,prompt=#ff8800"
+#[cfg(feature = "cli")]
+pub(crate) fn parse_color_list(list: &str, colors: &mut HashMap<String, Color>) -> io::Result<()> {
+  for pair in list.split(',').filter(|pair| !pair.is_empty()) {
+    match pair.find('=') {
+      Some(idx) => {
+        colors.insert(pair[0..idx].to_string(), pair[idx + 1..].parse()?);
+      }
+      None => return other_error!("Invalid colour, expected name=colour: {}", pair),
+    }
+  }
+  validate_colors(colors)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn detects_the_colour_depth() {
+    assert_eq!(
+      ColorDepth::new(Some("truecolor"), Some(8)),
+      ColorDepth::TrueColor
+    );
+    assert_eq!(ColorDepth::new(Some("24bit"), None), ColorDepth::TrueColor);
+    assert_eq!(ColorDepth::new(None, Some(256)), ColorDepth::Indexed256);
+    assert_eq!(ColorDepth::new(Some("yes"), Some(16)), ColorDepth::Ansi16);
+    assert_eq!(ColorDepth::new(None, Some(8)), ColorDepth::Ansi8);
+    assert_eq!(ColorDepth::new(None, None), ColorDepth::Ansi8);
+  }
+
+  #[test]
+  fn downgrades_to_the_nearest_colour() {
+    let orange = Color::Rgb(255, 135, 0);
+    assert_eq!(orange.downgrade(ColorDepth::TrueColor), orange);
+    assert_eq!(
+      orange.downgrade(ColorDepth::Indexed256),
+      Color::Indexed(208)
+    );
+    assert_eq!(
+      Color::Rgb(48, 48, 48).downgrade(ColorDepth::Indexed256),
+      Color::Indexed(236)
+    );
+    assert_eq!(orange.downgrade(ColorDepth::Ansi16), Color::Ansi(3));
+    assert_eq!(
+      Color::Rgb(250, 10, 10).downgrade(ColorDepth::Ansi16),
+      Color::Ansi(9)
+    );
+    assert_eq!(
+      Color::Rgb(250, 10, 10).downgrade(ColorDepth::Ansi8),
+      Color::Ansi(1)
+    );
+
+    assert_eq!(
+      Color::Indexed(236).downgrade(ColorDepth::Indexed256),
+      Color::Indexed(236)
+    );
+    assert_eq!(
+      Color::Indexed(236).downgrade(ColorDepth::Ansi16),
+      Color::Ansi(0)
+    );
+    assert_eq!(
+      Color::Indexed(12).downgrade(ColorDepth::Ansi16),
+      Color::Ansi(12)
+    );
+    assert_eq!(Color::Ansi(12).downgrade(ColorDepth::Ansi8), Color::Ansi(4));
+    assert_eq!(Color::Default.downgrade(ColorDepth::Ansi8), Color::Default);
+  }
+}
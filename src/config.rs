@@ -1,37 +1,296 @@
-use crate::other_error;
+use crate::{
+  color::{self, Color},
+  other_error,
+};
 use serde::Deserialize;
-use std::{collections::HashMap, io};
-use toml;
-use xdg;
+use std::{collections::HashMap, convert::TryFrom, io, path::PathBuf};
 
+// Where the number of matches is shown
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum InfoStyle {
+  // after the criteria on the prompt line
+  #[default]
+  Inline,
+  // on its own line below the prompt
+  Row,
+  Hidden,
+}
+
+// Criteria used to order matches with equal scores, earlier criteria take precedence. Only the
+// command sets them.
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Tiebreak {
+  // prefer shorter choices
+  Length,
+  // prefer matches that start closer to the start of the choice
+  Begin,
+  // prefer matches that end closer to the end of the choice
+  End,
+  // prefer choices that appear earlier in the input
+  Index,
+}
+
+// Parse a comma separated list of tiebreak criteria e.g. "length,begin"
+#[cfg(feature = "cli")]
+pub(crate) fn parse_tiebreak_list(list: &str) -> io::Result<Vec<Tiebreak>> {
+  list
+    .split(',')
+    .map(|name| match name {
+      "length" => Ok(Tiebreak::Length),
+      "begin" => Ok(Tiebreak::Begin),
+      "end" => Ok(Tiebreak::End),
+      "index" => Ok(Tiebreak::Index),
+      _ => other_error!(
+        "Invalid tiebreak, expected length, begin, end or index: {}",
+        name
+      ),
+    })
+    .collect()
+}
+
+// Which occurrence of a duplicated choice is kept
+#[cfg(feature = "cli")]
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Dedup {
+  First,
+  Last,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum BorderStyle {
+  #[default]
+  None,
+  Rounded,
+  Sharp,
+}
+
+// Space around each side of the window
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(try_from = "SpacingValue")]
+pub(crate) struct Spacing {
+  pub top: usize,
+  pub right: usize,
+  pub bottom: usize,
+  pub left: usize,
+}
+
+// Spacing can be a single number for all sides or a list of numbers in the same order as css
+// i.e. [vertical, horizontal] or [top, right, bottom, left]
 #[derive(Deserialize)]
-#[serde(deny_unknown_fields)]
+#[serde(untagged)]
+enum SpacingValue {
+  All(usize),
+  List(Vec<usize>),
+}
+
+impl TryFrom<SpacingValue> for Spacing {
+  type Error = String;
+
+  fn try_from(value: SpacingValue) -> Result<Self, Self::Error> {
+    let sides = match value {
+      SpacingValue::All(size) => vec![size],
+      SpacingValue::List(sides) => sides,
+    };
+
+    match sides[..] {
+      [size] => Ok(Spacing {
+        top: size,
+        right: size,
+        bottom: size,
+        left: size,
+      }),
+      [vertical, horizontal] => Ok(Spacing {
+        top: vertical,
+        right: horizontal,
+        bottom: vertical,
+        left: horizontal,
+      }),
+      [top, right, bottom, left] => Ok(Spacing {
+        top,
+        right,
+        bottom,
+        left,
+      }),
+      _ => Err("spacing must be a number or a list of 1, 2 or 4 numbers".to_string()),
+    }
+  }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
 pub(crate) struct WindowConfig {
   pub height: i32,
+  pub info: InfoStyle,
+  pub prompt: String,
+  // shown beside the selected option
+  pub pointer: String,
+  // shown beside options that have been marked
+  pub marker: String,
+  pub border: BorderStyle,
+  // space outside of the border
+  pub margin: Spacing,
+  // space inside of the border
+  pub padding: Spacing,
+  // the most times the window is drawn each second, 0 for no limit
+  pub max_fps: u32,
+  // wrap each frame in a synchronized update, some terminals show the escape sequences
+  pub synchronized_output: bool,
 }
 
 impl Default for WindowConfig {
   fn default() -> Self {
-    Self { height: 0 }
+    Self {
+      height: 0,
+      info: InfoStyle::Inline,
+      prompt: "> ".to_string(),
+      pointer: ">".to_string(),
+      marker: "*".to_string(),
+      border: BorderStyle::None,
+      margin: Default::default(),
+      padding: Default::default(),
+      max_fps: 60,
+      synchronized_output: true,
+    }
   }
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct HistoryConfig {
+  // record accepted queries so they can be recalled with the history actions
+  pub enabled: bool,
+  // defaults to a file named after the context in the naru/history data directory
+  pub file: Option<PathBuf>,
+  // keeps separate histories e.g. for each editor command that uses naru
+  pub context: String,
+  // number of queries to keep, the oldest are removed first
+  pub max_size: usize,
+}
+
+impl Default for HistoryConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      file: None,
+      context: "default".to_string(),
+      max_size: 1000,
+    }
+  }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RankingConfig {
+  // boost the scores of choices that were selected frequently and recently
+  pub frecency: bool,
+  // defaults to naru/frecency in the data directory
+  pub file: Option<PathBuf>,
+}
+
+/// Options for the selector. These are loaded from the configuration file with [`load_config`] and
+/// passed to [`Naru::config`](crate::Naru::config), the builder methods of
+/// [`Naru`](crate::Naru) override them.
+#[derive(Clone, Default, Deserialize)]
 #[serde(deny_unknown_fields)]
-pub(crate) struct Config {
+pub struct Config {
+  #[serde(default)]
+  pub(crate) window: WindowConfig,
+
   #[serde(default)]
-  pub window: WindowConfig,
+  pub(crate) bindings: HashMap<String, String>,
 
   #[serde(default)]
-  pub bindings: HashMap<String, String>,
+  pub(crate) colors: HashMap<String, Color>,
+
+  #[serde(default)]
+  pub(crate) history: HistoryConfig,
+
+  #[serde(default)]
+  pub(crate) ranking: RankingConfig,
+
+  // strip escape sequences from choices before matching and draw them with their colours
+  #[serde(skip)]
+  pub(crate) ansi: bool,
+
+  // use attributes like underline and invert instead of colours
+  #[serde(skip)]
+  pub(crate) no_color: bool,
+
+  // text shown above the options
+  #[serde(skip)]
+  pub(crate) header: Option<String>,
+
+  // number of lines at the start of the input to show above the options instead of as choices
+  #[cfg(feature = "cli")]
+  #[serde(skip)]
+  pub(crate) header_lines: usize,
+
+  // criteria used to order matches with equal scores, ties are otherwise kept in input order
+  #[serde(skip)]
+  pub(crate) tiebreak: Vec<Tiebreak>,
+
+  // keep matches in input order instead of sorting them by score
+  #[serde(skip)]
+  pub(crate) no_sort: bool,
+
+  // reverse the order of the choices
+  #[cfg(feature = "cli")]
+  #[serde(skip)]
+  pub(crate) tac: bool,
+
+  // remove duplicate choices
+  #[cfg(feature = "cli")]
+  #[serde(skip)]
+  pub(crate) dedup: Option<Dedup>,
+
+  // read choices separated by null characters instead of newlines
+  #[cfg(feature = "cli")]
+  #[serde(skip)]
+  pub(crate) read0: bool,
+
+  // output selected choices followed by null characters instead of newlines
+  #[cfg(feature = "cli")]
+  #[serde(skip)]
+  pub(crate) print0: bool,
+
+  // keep leading and trailing whitespace in choices
+  #[cfg(feature = "cli")]
+  #[serde(skip)]
+  pub(crate) no_trim: bool,
+
+  // allow marking several choices with toggle-mark
+  #[serde(skip)]
+  pub(crate) multi: bool,
+
+  // keys that accept the selection as well as enter, paired with their names. The name of the key
+  // that was pressed is output before the selection.
+  #[serde(skip)]
+  pub(crate) expect: Vec<(String, String)>,
+
+  // output the criteria on the first line
+  #[cfg(feature = "cli")]
+  #[serde(skip)]
+  pub(crate) print_query: bool,
 }
 
-impl Default for Config {
-  fn default() -> Self {
-    Self {
-      window: Default::default(),
-      bindings: HashMap::new(),
+// Parse a key like "c-a" into the corresponding control code
+fn parse_key(key: &str) -> io::Result<String> {
+  if key.starts_with("c-") {
+    if key.len() != 3 {
+      return other_error!("Invalid key, only one character allowed after c-: {}", key);
+    }
+    let last_char = key.bytes().last().unwrap();
+    if !last_char.is_ascii_lowercase() {
+      return other_error!("Invalid key, only a-z allowed after c-: {}", key);
     }
+
+    // I feel like there should be a better way to do this
+    Ok(String::from_utf8_lossy(&[last_char - b'`']).to_string())
+  } else {
+    other_error!("Invalid key, must start with c-: {}", key)
   }
 }
 
@@ -40,29 +299,24 @@ fn parse_bindings(bindings: HashMap<String, String>) -> io::Result<HashMap<Strin
   bindings
     .iter()
     .map(|(binding, action_name): (&String, &String)| {
-      if binding.starts_with("c-") {
-        if binding.len() != 3 {
-          return other_error!(
-            "Invalid binding, only one character allowed after c-: {}",
-            binding
-          );
-        }
-        let last_char = binding.bytes().last().unwrap();
-        if last_char < b'a' || last_char > b'z' {
-          return other_error!("Invalid binding, only a-z allowed after c-: {}", binding);
-        }
-
-        // I feel like there should be a better way to do this
-        let control_code = String::from_utf8_lossy(&[last_char - b'`']).to_string();
-        return Ok((control_code, action_name.clone()));
-      } else {
-        return other_error!("Invalid binding, must start with c-: {}", binding);
-      }
+      Ok((parse_key(binding)?, action_name.clone()))
     })
     .collect()
 }
 
-pub(crate) fn load_config() -> io::Result<Config> {
+// Parse a comma separated list of keys e.g. "c-t,c-v" into pairs of control codes and key names
+#[cfg(feature = "cli")]
+pub(crate) fn parse_key_list(list: &str) -> io::Result<Vec<(String, String)>> {
+  list
+    .split(',')
+    .filter(|key| !key.is_empty())
+    .map(|key| Ok((parse_key(key)?, key.to_string())))
+    .collect()
+}
+
+/// Load the options from `naru.toml` in the XDG configuration directories, the defaults are used
+/// when there is no file
+pub fn load_config() -> io::Result<Config> {
   let xdg_dirs = xdg::BaseDirectories::new()?;
   let cfg_file = xdg_dirs.find_config_file("naru.toml");
 
@@ -74,7 +328,71 @@ pub(crate) fn load_config() -> io::Result<Config> {
       let content = std::fs::read_to_string(path)?;
       let mut parsed_config: Config = toml::from_str(&content)?;
       parsed_config.bindings = parse_bindings(parsed_config.bindings)?;
+      color::validate_colors(&parsed_config.colors)?;
       Ok(parsed_config)
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn spacing(toml: &str) -> Result<Spacing, toml::de::Error> {
+    let window: WindowConfig = toml::from_str(toml)?;
+    Ok(window.margin)
+  }
+
+  #[test]
+  fn parses_spacing_like_css() {
+    let sides = |spacing: Spacing| (spacing.top, spacing.right, spacing.bottom, spacing.left);
+    assert_eq!(sides(spacing("margin = 1").unwrap()), (1, 1, 1, 1));
+    assert_eq!(sides(spacing("margin = [1]").unwrap()), (1, 1, 1, 1));
+    assert_eq!(sides(spacing("margin = [1, 2]").unwrap()), (1, 2, 1, 2));
+    assert_eq!(
+      sides(spacing("margin = [1, 2, 3, 4]").unwrap()),
+      (1, 2, 3, 4)
+    );
+    assert!(spacing("margin = [1, 2, 3]").is_err());
+    assert!(spacing("margin = []").is_err());
+  }
+
+  #[cfg(feature = "cli")]
+  #[test]
+  fn parses_tiebreak_lists() {
+    assert_eq!(
+      parse_tiebreak_list("length,begin,end,index").unwrap(),
+      [
+        Tiebreak::Length,
+        Tiebreak::Begin,
+        Tiebreak::End,
+        Tiebreak::Index
+      ]
+    );
+    assert_eq!(
+      parse_tiebreak_list("length,size").unwrap_err().to_string(),
+      "Invalid tiebreak, expected length, begin, end or index: size"
+    );
+    assert!(parse_tiebreak_list("").is_err());
+  }
+
+  #[cfg(feature = "cli")]
+  #[test]
+  fn parses_key_lists() {
+    let keys = |list| parse_key_list(list).unwrap();
+    assert_eq!(
+      keys("c-t,c-v"),
+      [
+        ("\x14".to_string(), "c-t".to_string()),
+        ("\x16".to_string(), "c-v".to_string())
+      ]
+    );
+    // empty entries e.g. from a trailing comma are skipped
+    assert_eq!(keys(",c-t,,"), [("\x14".to_string(), "c-t".to_string())]);
+    assert!(keys("").is_empty());
+
+    for invalid in ["t", "c-", "c-tv", "c-T", "c-1", "c-t,x"] {
+      assert!(parse_key_list(invalid).is_err(), "{} was accepted", invalid);
+    }
+  }
+}
@@ -0,0 +1,29 @@
+use std::{
+  fs,
+  io::{self, Write},
+  path::{Path, PathBuf},
+};
+
+// The path of a file in the naru data directory
+pub(crate) fn data_file_path(name: &str) -> io::Result<PathBuf> {
+  Ok(
+    xdg::BaseDirectories::with_prefix("naru")?
+      .get_data_home()
+      .join(name),
+  )
+}
+
+// Replace the contents of a file, the contents are written to a temporary file which is then
+// renamed so that concurrent runs never see a half written file
+pub(crate) fn write_data_file(path: &Path, content: &str) -> io::Result<()> {
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir)?;
+  }
+
+  let mut tmp_path = path.to_path_buf().into_os_string();
+  tmp_path.push(format!(".{}.tmp", std::process::id()));
+  let mut file = fs::File::create(&tmp_path)?;
+  file.write_all(content.as_bytes())?;
+  file.sync_all()?;
+  fs::rename(&tmp_path, path)
+}
@@ -0,0 +1,95 @@
+use crate::{ansi::AnsiString, config::Config, item::SelectorItem, other_error};
+use std::{
+  borrow::Cow,
+  fs::OpenOptions,
+  io,
+  io::{BufRead, BufReader, Write},
+};
+use sublime_fuzzy::best_match;
+
+// Whether the terminal cannot interpret escape sequences and needs the numbered list fallback
+pub(crate) fn is_dumb_terminal() -> bool {
+  std::env::var("TERM").is_ok_and(|term| term == "dumb")
+}
+
+// A fallback for terminals that cannot interpret escape sequences. The choices are listed with
+// numbers and a line is read from the terminal, a number selects the corresponding choice and
+// anything else is used as criteria to filter the list. Returns the index of the selected choice
+// and the criteria the list was filtered with.
+pub(crate) fn select<T: SelectorItem>(
+  tty_path: &str,
+  choices: &[T],
+  header_lines: &[String],
+  conf: &Config,
+) -> io::Result<(usize, String)> {
+  let mut fout = OpenOptions::new().write(true).open(tty_path)?;
+  let mut fin = BufReader::new(OpenOptions::new().read(true).open(tty_path)?);
+
+  let texts: Vec<Cow<str>> = choices
+    .iter()
+    .map(|choice| {
+      if conf.ansi {
+        Cow::Owned(AnsiString::new(choice.display_text()).text)
+      } else {
+        Cow::Borrowed(choice.display_text())
+      }
+    })
+    .collect();
+
+  let mut options: Vec<usize> = (0..choices.len()).collect();
+  let mut input = String::new();
+  let mut query = String::new();
+  let header = conf
+    .header
+    .iter()
+    .flat_map(|header| header.lines())
+    .chain(header_lines.iter().map(|header_line| header_line.as_str()));
+  for header_line in header {
+    if conf.ansi {
+      writeln!(fout, "     {}", AnsiString::new(header_line).text)?;
+    } else {
+      writeln!(fout, "     {}", header_line)?;
+    }
+  }
+
+  loop {
+    for (number, &choice_idx) in options.iter().enumerate() {
+      writeln!(fout, "{:>3}) {}", number + 1, texts[choice_idx])?;
+    }
+    write!(fout, "> ")?;
+    fout.flush()?;
+
+    input.clear();
+    if fin.read_line(&mut input)? == 0 {
+      return other_error!("No selection made");
+    }
+    let criteria = input.trim();
+
+    if criteria.is_empty() {
+      if let Some(&choice_idx) = options.first() {
+        return Ok((choice_idx, query));
+      }
+    } else if let Ok(number) = criteria.parse::<usize>() {
+      if number >= 1 && number <= options.len() {
+        return Ok((options[number - 1], query));
+      }
+      writeln!(fout, "Invalid number: {}", number)?;
+    } else {
+      let mut matches: Vec<(usize, isize)> = texts
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, text)| {
+          let text = if choices[idx].highlights_display_text() {
+            text
+          } else {
+            choices[idx].match_text()
+          };
+          best_match(criteria, text).map(|v| (idx, v.score()))
+        })
+        .collect();
+      matches.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+      options = matches.into_iter().map(|(idx, _)| idx).collect();
+      query = criteria.to_string();
+    }
+  }
+}
@@ -0,0 +1,391 @@
+use crate::{
+  color::{Color, ColorDepth},
+  terminfo::{self, TermInfo},
+};
+use std::{
+  env,
+  io::{self, Write},
+};
+
+// The escape sequences for the operations of a terminal. They are read from the terminfo entry of
+// the terminal when it has one and the sequences understood by xterm compatible terminals are used
+// for anything else.
+pub(crate) struct Capabilities {
+  clear_line: Vec<u8>,
+  clear_screen_below: Vec<u8>,
+  // the parameterized sequences take the column or row count
+  column_address: Vec<u8>,
+  parm_up_cursor: Vec<u8>,
+  parm_down_cursor: Vec<u8>,
+  enter_am_mode: Vec<u8>,
+  exit_am_mode: Vec<u8>,
+  keypad_xmit: Vec<u8>,
+  keypad_local: Vec<u8>,
+  enter_bold_mode: Vec<u8>,
+  enter_underline_mode: Vec<u8>,
+  enter_reverse_mode: Vec<u8>,
+  exit_attribute_mode: Vec<u8>,
+  // used for the colours below max_colors, SGR sequences are written for the others
+  set_a_foreground: Option<Vec<u8>>,
+  set_a_background: Option<Vec<u8>>,
+  max_colors: i32,
+  // colours are downgraded to those that the terminal can show
+  color_depth: ColorDepth,
+}
+
+impl Capabilities {
+  // the capabilities of the terminal described by TERM and COLORTERM
+  pub fn from_env(info: Option<&TermInfo>) -> Capabilities {
+    Capabilities::new(info, env::var("COLORTERM").ok().as_deref())
+  }
+
+  pub fn new(info: Option<&TermInfo>, colorterm: Option<&str>) -> Capabilities {
+    let string = |cap, fallback: &[u8]| {
+      info
+        .and_then(|info| info.string(cap))
+        .unwrap_or(fallback)
+        .to_vec()
+    };
+    let color = |cap| info.and_then(|info| info.string(cap)).map(<[u8]>::to_vec);
+
+    Capabilities {
+      clear_line: string(terminfo::CLR_EOL, b"\x1b[K"),
+      clear_screen_below: string(terminfo::CLR_EOS, b"\x1b[J"),
+      column_address: string(terminfo::COLUMN_ADDRESS, b"\x1b[%i%p1%dG"),
+      parm_up_cursor: string(terminfo::PARM_UP_CURSOR, b"\x1b[%p1%dA"),
+      parm_down_cursor: string(terminfo::PARM_DOWN_CURSOR, b"\x1b[%p1%dB"),
+      enter_am_mode: string(terminfo::ENTER_AM_MODE, b"\x1b[?7h"),
+      exit_am_mode: string(terminfo::EXIT_AM_MODE, b"\x1b[?7l"),
+      // the cursor keys are read in either mode so nothing is written without an entry
+      keypad_xmit: string(terminfo::KEYPAD_XMIT, b""),
+      keypad_local: string(terminfo::KEYPAD_LOCAL, b""),
+      enter_bold_mode: string(terminfo::ENTER_BOLD_MODE, b"\x1b[1m"),
+      enter_underline_mode: string(terminfo::ENTER_UNDERLINE_MODE, b"\x1b[4m"),
+      enter_reverse_mode: string(terminfo::ENTER_REVERSE_MODE, b"\x1b[7m"),
+      exit_attribute_mode: string(terminfo::EXIT_ATTRIBUTE_MODE, b"\x1b[0m"),
+      set_a_foreground: color(terminfo::SET_A_FOREGROUND),
+      set_a_background: color(terminfo::SET_A_BACKGROUND),
+      max_colors: info
+        .and_then(|info| info.number(terminfo::MAX_COLORS))
+        .unwrap_or(0),
+      // without an entry only the 8 basic colours are assumed unless COLORTERM says otherwise
+      color_depth: ColorDepth::new(
+        colorterm,
+        info.and_then(|info| info.number(terminfo::MAX_COLORS)),
+      ),
+    }
+  }
+}
+
+// the screen of the tests shows every colour
+#[cfg(test)]
+impl Default for Capabilities {
+  fn default() -> Capabilities {
+    Capabilities::new(None, Some("truecolor"))
+  }
+}
+
+// Writes the output of a terminal using its escape sequences. Tty writes to the terminal device
+// through a buffer and the tests write to an emulated screen.
+pub(crate) struct EscapeWriter<W: Write> {
+  out: W,
+  caps: Capabilities,
+  // the foreground colour set last so that setting it again can be skipped, None when text with
+  // its own escape sequences may have changed it
+  fg_color: Option<Color>,
+  // parameterized capabilities are expanded here, it's reused so that moving the cursor or
+  // changing colour doesn't allocate
+  sequence: Vec<u8>,
+}
+
+impl<W: Write> EscapeWriter<W> {
+  #[cfg(test)]
+  pub fn new(out: W) -> EscapeWriter<W> {
+    EscapeWriter::with_capabilities(out, Capabilities::default())
+  }
+
+  pub fn with_capabilities(out: W, caps: Capabilities) -> EscapeWriter<W> {
+    EscapeWriter {
+      out,
+      caps,
+      fg_color: Some(Color::Default),
+      sequence: Vec::new(),
+    }
+  }
+
+  #[cfg(test)]
+  pub fn get_ref(&self) -> &W {
+    &self.out
+  }
+
+  #[cfg(test)]
+  pub fn get_mut(&mut self) -> &mut W {
+    &mut self.out
+  }
+
+  pub fn print(&mut self, text: &str) -> io::Result<()> {
+    if text.contains("\x1b[") {
+      self.fg_color = None;
+    }
+    self.out.write_all(text.as_bytes())
+  }
+
+  // Remove everything after cursor
+  pub fn clearline(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.clear_line)
+  }
+
+  // Remove everything after cursor including all lines below it
+  pub fn clear_screen_below(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.clear_screen_below)
+  }
+
+  // Remove everything after cursor then move to next line
+  pub fn newline(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.clear_line)?;
+    self.out.write_all(b"\n")
+  }
+
+  pub fn set_col(&mut self, col: usize) -> io::Result<()> {
+    write_expanded(
+      &mut self.out,
+      &mut self.sequence,
+      &self.caps.column_address,
+      col as i32,
+    )
+  }
+
+  pub fn move_up(&mut self, row_count: usize) -> io::Result<()> {
+    // a count of 0 is treated as 1 by terminals
+    if row_count == 0 {
+      return Ok(());
+    }
+    write_expanded(
+      &mut self.out,
+      &mut self.sequence,
+      &self.caps.parm_up_cursor,
+      row_count as i32,
+    )
+  }
+
+  pub fn move_down(&mut self, row_count: usize) -> io::Result<()> {
+    // a count of 0 is treated as 1 by terminals
+    if row_count == 0 {
+      return Ok(());
+    }
+    write_expanded(
+      &mut self.out,
+      &mut self.sequence,
+      &self.caps.parm_down_cursor,
+      row_count as i32,
+    )
+  }
+
+  pub fn set_no_wrap(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.exit_am_mode)
+  }
+
+  pub fn set_wrap(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.enter_am_mode)
+  }
+
+  // make the keypad send the key codes in the terminfo entry
+  pub fn enter_keypad_mode(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.keypad_xmit)
+  }
+
+  pub fn exit_keypad_mode(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.keypad_local)
+  }
+
+  // terminals without support for synchronized output ignore the mode
+  pub fn begin_synchronized_update(&mut self) -> io::Result<()> {
+    self.out.write_all(b"\x1b[?2026h")
+  }
+
+  pub fn end_synchronized_update(&mut self) -> io::Result<()> {
+    self.out.write_all(b"\x1b[?2026l")
+  }
+
+  fn sgr(&mut self, code: u8) -> io::Result<()> {
+    write!(self.out, "\x1b[{}m", code)
+  }
+
+  // Set the foreground when base is 30 or the background when base is 40
+  fn sgr_color(&mut self, base: u8, color: Color) -> io::Result<()> {
+    let color = color.downgrade(self.caps.color_depth);
+    let set_color = if base == 30 {
+      &self.caps.set_a_foreground
+    } else {
+      &self.caps.set_a_background
+    };
+    if let (Some(set_color), Color::Ansi(idx) | Color::Indexed(idx)) = (set_color, color) {
+      if i32::from(idx) < self.caps.max_colors {
+        return write_expanded(&mut self.out, &mut self.sequence, set_color, i32::from(idx));
+      }
+    }
+
+    match color {
+      Color::Default => self.sgr(base + 9),
+      Color::Ansi(idx) if idx < 8 => self.sgr(base + idx),
+      // bright variants start at 90 for foreground and 100 for background
+      Color::Ansi(idx) => self.sgr(base + 60 + idx - 8),
+      Color::Indexed(idx) => write!(self.out, "\x1b[{};5;{}m", base + 8, idx),
+      Color::Rgb(r, g, b) => write!(self.out, "\x1b[{};2;{};{};{}m", base + 8, r, g, b),
+    }
+  }
+
+  pub fn set_fg(&mut self, color: Color) -> io::Result<()> {
+    if self.fg_color != Some(color) {
+      self.sgr_color(30, color)?;
+      self.fg_color = Some(color);
+    }
+    Ok(())
+  }
+
+  pub fn set_bg(&mut self, color: Color) -> io::Result<()> {
+    self.sgr_color(40, color)
+  }
+
+  pub fn set_bold(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.enter_bold_mode)
+  }
+
+  pub fn set_underline(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.enter_underline_mode)
+  }
+
+  pub fn set_invert(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.enter_reverse_mode)
+  }
+
+  pub fn set_normal(&mut self) -> io::Result<()> {
+    self.out.write_all(&self.caps.exit_attribute_mode)?;
+    self.fg_color = Some(Color::Default);
+    Ok(())
+  }
+
+  pub fn flush(&mut self) -> io::Result<()> {
+    self.out.flush()
+  }
+}
+
+// Expand a capability with one parameter into the buffer and write it
+fn write_expanded<W: Write>(
+  out: &mut W,
+  buffer: &mut Vec<u8>,
+  cap: &[u8],
+  param: i32,
+) -> io::Result<()> {
+  buffer.clear();
+  terminfo::expand(cap, &[param], buffer);
+  out.write_all(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn written<F: FnOnce(&mut EscapeWriter<Vec<u8>>) -> io::Result<()>>(draw: F) -> String {
+    let mut writer = EscapeWriter::new(Vec::new());
+    draw(&mut writer).unwrap();
+    String::from_utf8(writer.out).unwrap()
+  }
+
+  #[test]
+  fn prints_text_containing_nul_bytes() {
+    assert_eq!(written(|writer| writer.print("a\0b")), "a\0b");
+  }
+
+  #[test]
+  fn writes_colours() {
+    let output = written(|writer| {
+      writer.set_fg(Color::Ansi(1))?;
+      writer.set_fg(Color::Ansi(1))?;
+      writer.set_fg(Color::Ansi(9))?;
+      writer.set_bg(Color::Indexed(236))?;
+      writer.set_fg(Color::Rgb(1, 2, 3))?;
+      writer.set_normal()?;
+      writer.set_fg(Color::Rgb(1, 2, 3))
+    });
+    assert_eq!(
+      output,
+      "\x1b[31m\x1b[91m\x1b[48;5;236m\x1b[38;2;1;2;3m\x1b[0m\x1b[38;2;1;2;3m"
+    );
+  }
+
+  #[test]
+  fn sets_the_colour_again_after_printing_escape_sequences() {
+    let output = written(|writer| {
+      writer.set_fg(Color::Ansi(1))?;
+      writer.print("\x1b[32mgreen")?;
+      writer.set_fg(Color::Ansi(1))
+    });
+    assert_eq!(output, "\x1b[31m\x1b[32mgreen\x1b[31m");
+  }
+
+  #[test]
+  fn writes_the_sequences_of_the_capabilities() {
+    let caps = Capabilities {
+      parm_up_cursor: b"\x1bA%p1%d".to_vec(),
+      exit_attribute_mode: b"\x1b[m\x0f".to_vec(),
+      set_a_foreground: Some(b"\x1b[3%p1%dm".to_vec()),
+      max_colors: 8,
+      ..Capabilities::default()
+    };
+    let mut writer = EscapeWriter::with_capabilities(Vec::new(), caps);
+    writer.move_up(3).unwrap();
+    writer.move_up(12).unwrap();
+    writer.set_fg(Color::Indexed(2)).unwrap();
+    // colours beyond those of the terminal are written as SGR sequences
+    writer.set_fg(Color::Ansi(9)).unwrap();
+    writer.set_normal().unwrap();
+    assert_eq!(
+      String::from_utf8(writer.out).unwrap(),
+      "\x1bA3\x1bA12\x1b[32m\x1b[91m\x1b[m\x0f"
+    );
+  }
+
+  #[test]
+  fn downgrades_colours_to_the_colour_depth() {
+    let caps = Capabilities {
+      set_a_foreground: Some(b"\x1b[3%p1%dm".to_vec()),
+      set_a_background: Some(b"\x1b[4%p1%dm".to_vec()),
+      max_colors: 8,
+      color_depth: ColorDepth::Ansi8,
+      ..Capabilities::default()
+    };
+    let mut writer = EscapeWriter::with_capabilities(Vec::new(), caps);
+    writer.set_fg(Color::Rgb(250, 10, 10)).unwrap();
+    writer.set_bg(Color::Indexed(236)).unwrap();
+    writer.set_fg(Color::Ansi(12)).unwrap();
+    assert_eq!(
+      String::from_utf8(writer.out).unwrap(),
+      "\x1b[31m\x1b[40m\x1b[34m"
+    );
+  }
+
+  #[test]
+  fn assumes_8_colours_without_an_entry() {
+    assert_eq!(Capabilities::new(None, None).color_depth, ColorDepth::Ansi8);
+    assert_eq!(
+      Capabilities::new(None, Some("24bit")).color_depth,
+      ColorDepth::TrueColor
+    );
+
+    let mut writer = EscapeWriter::with_capabilities(Vec::new(), Capabilities::new(None, None));
+    writer.set_fg(Color::Indexed(9)).unwrap();
+    assert_eq!(String::from_utf8(writer.out).unwrap(), "\x1b[31m");
+  }
+
+  #[test]
+  fn skips_moves_of_no_rows() {
+    let output = written(|writer| {
+      writer.move_up(0)?;
+      writer.move_down(0)?;
+      writer.move_up(2)?;
+      writer.set_col(0)
+    });
+    assert_eq!(output, "\x1b[2A\x1b[1G");
+  }
+}
@@ -0,0 +1,197 @@
+use crate::{
+  config::RankingConfig,
+  data_file::{data_file_path, write_data_file},
+};
+use std::{
+  collections::HashMap,
+  fs, io,
+  path::PathBuf,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+// when the counts add up to more than this they are all reduced so that choices which are no
+// longer used are eventually forgotten
+const MAX_TOTAL_COUNT: f64 = 10_000.0;
+const AGING_FACTOR: f64 = 0.9;
+
+// scales the frecency into the range of scores given to matches
+const BOOST_WEIGHT: f64 = 32.0;
+
+struct Entry {
+  count: f64,
+  // seconds since the unix epoch
+  last_used: u64,
+}
+
+// How frequently and recently each choice was selected in previous runs. The entries are stored
+// one per line as the count, the time of the last use and the choice separated by tabs.
+pub(crate) struct Frecency {
+  path: PathBuf,
+  entries: HashMap<String, Entry>,
+  now: u64,
+}
+
+impl Frecency {
+  pub fn load(conf: &RankingConfig) -> io::Result<Frecency> {
+    let path = match &conf.file {
+      Some(file) => file.clone(),
+      None => data_file_path("frecency")?,
+    };
+
+    let content = match fs::read_to_string(&path) {
+      Ok(content) => content,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+      Err(err) => return Err(err),
+    };
+    let entries = content
+      .lines()
+      .filter_map(|line| {
+        let mut fields = line.splitn(3, '\t');
+        let count = fields.next()?.parse().ok()?;
+        let last_used = fields.next()?.parse().ok()?;
+        let choice = fields.next()?;
+        Some((choice.to_string(), Entry { count, last_used }))
+      })
+      .collect();
+
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |duration| duration.as_secs());
+
+    Ok(Frecency { path, entries, now })
+  }
+
+  // The amount added to the score of matches for the choice, choices that were never selected
+  // get no boost
+  pub fn boost(&self, choice: &str) -> isize {
+    match self.entries.get(choice) {
+      Some(entry) => {
+        let age = self.now.saturating_sub(entry.last_used);
+        let recency = if age < HOUR {
+          4.0
+        } else if age < DAY {
+          2.0
+        } else if age < WEEK {
+          0.5
+        } else {
+          0.25
+        };
+        (BOOST_WEIGHT * (1.0 + entry.count * recency).ln()) as isize
+      }
+      None => 0,
+    }
+  }
+
+  // Record the choices as selected now
+  pub fn add<'a, I: IntoIterator<Item = &'a str>>(&mut self, choices: I) -> io::Result<()> {
+    let now = self.now;
+    for choice in choices {
+      // the file is line based so choices spanning lines cannot be stored
+      if choice.contains('\n') {
+        continue;
+      }
+      let entry = self.entries.entry(choice.to_string()).or_insert(Entry {
+        count: 0.0,
+        last_used: now,
+      });
+      entry.count += 1.0;
+      entry.last_used = now;
+    }
+
+    let total_count: f64 = self.entries.values().map(|entry| entry.count).sum();
+    if total_count > MAX_TOTAL_COUNT {
+      for entry in self.entries.values_mut() {
+        entry.count *= AGING_FACTOR;
+      }
+      self.entries.retain(|_, entry| entry.count >= 1.0);
+    }
+
+    self.save()
+  }
+
+  fn save(&self) -> io::Result<()> {
+    let mut content = String::new();
+    for (choice, entry) in &self.entries {
+      content.push_str(&format!(
+        "{}\t{}\t{}\n",
+        entry.count, entry.last_used, choice
+      ));
+    }
+    write_data_file(&self.path, &content)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+
+  const NOW: u64 = 100 * WEEK;
+
+  fn frecency(entries: &[(&str, f64, u64)]) -> Frecency {
+    let file = format!("naru-frecency-{}", std::process::id());
+    Frecency {
+      path: env::temp_dir().join(file),
+      entries: entries
+        .iter()
+        .map(|&(choice, count, age)| {
+          let last_used = NOW - age;
+          (choice.to_string(), Entry { count, last_used })
+        })
+        .collect(),
+      now: NOW,
+    }
+  }
+
+  #[test]
+  fn boosts_choices_selected_more_often() {
+    let frecency = frecency(&[("once", 1.0, DAY), ("often", 10.0, DAY)]);
+    assert_eq!(frecency.boost("never"), 0);
+    assert!(frecency.boost("once") > 0);
+    assert!(frecency.boost("often") > frecency.boost("once"));
+  }
+
+  #[test]
+  fn boosts_choices_selected_recently() {
+    let frecency = frecency(&[
+      ("hour", 5.0, 0),
+      ("day", 5.0, HOUR),
+      ("week", 5.0, DAY),
+      ("older", 5.0, WEEK),
+    ]);
+    let boosts: Vec<isize> = ["hour", "day", "week", "older"]
+      .iter()
+      .map(|choice| frecency.boost(choice))
+      .collect();
+    assert!(
+      boosts.windows(2).all(|pair| pair[0] > pair[1]),
+      "{:?}",
+      boosts
+    );
+  }
+
+  #[test]
+  fn counts_selections_and_ages_the_counts() -> io::Result<()> {
+    let mut frecency = frecency(&[("old", 3.0, WEEK)]);
+    frecency.add(["old", "new"])?;
+    assert_eq!(frecency.entries["old"].count, 4.0);
+    assert_eq!(frecency.entries["old"].last_used, NOW);
+    assert_eq!(frecency.entries["new"].count, 1.0);
+
+    // once the total is too high every count is reduced and the rarely used are forgotten
+    frecency.entries.get_mut("old").unwrap().count = MAX_TOTAL_COUNT;
+    frecency.add(["rare"])?;
+    fs::remove_file(&frecency.path)?;
+    assert_eq!(
+      frecency.entries["old"].count,
+      MAX_TOTAL_COUNT * AGING_FACTOR
+    );
+    assert!(!frecency.entries.contains_key("new"));
+    assert!(!frecency.entries.contains_key("rare"));
+    Ok(())
+  }
+}
@@ -0,0 +1,105 @@
+use crate::{
+  config::HistoryConfig,
+  data_file::{data_file_path, write_data_file},
+  other_error,
+};
+use std::{fs, io, path::PathBuf};
+
+// Queries that were accepted in previous runs, stored one per line with the oldest first
+pub(crate) struct History {
+  path: PathBuf,
+  max_size: usize,
+  pub entries: Vec<String>,
+}
+
+impl History {
+  pub fn load(conf: &HistoryConfig) -> io::Result<History> {
+    let path = match &conf.file {
+      Some(file) => file.clone(),
+      None => {
+        if conf.context.is_empty() || conf.context.contains('/') {
+          return other_error!("Invalid history context: {}", conf.context);
+        }
+        data_file_path("history")?.join(&conf.context)
+      }
+    };
+
+    let entries = match fs::read_to_string(&path) {
+      Ok(content) => content.lines().map(|line| line.to_string()).collect(),
+      Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+      Err(err) => return Err(err),
+    };
+
+    Ok(History {
+      path,
+      max_size: conf.max_size,
+      entries,
+    })
+  }
+
+  // Record a query as the most recent entry, moving it there if it was already recorded
+  pub fn add(&mut self, query: &str) -> io::Result<()> {
+    // the file is line based so queries spanning lines cannot be stored
+    if query.is_empty() || query.contains('\n') {
+      return Ok(());
+    }
+
+    self.entries.retain(|entry| entry != query);
+    self.entries.push(query.to_string());
+    if self.entries.len() > self.max_size {
+      self.entries.drain(0..self.entries.len() - self.max_size);
+    }
+    self.save()
+  }
+
+  fn save(&self) -> io::Result<()> {
+    let mut content = String::new();
+    for entry in &self.entries {
+      content.push_str(entry);
+      content.push('\n');
+    }
+    write_data_file(&self.path, &content)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+
+  fn load(name: &str, max_size: usize) -> History {
+    let file = format!("naru-history-{}-{}", name, std::process::id());
+    let conf = HistoryConfig {
+      enabled: true,
+      file: Some(env::temp_dir().join(file)),
+      max_size,
+      ..HistoryConfig::default()
+    };
+    History::load(&conf).unwrap()
+  }
+
+  #[test]
+  fn keeps_the_most_recent_queries() -> io::Result<()> {
+    let mut history = load("recent", 2);
+    for query in ["a", "b", "c"] {
+      history.add(query)?;
+    }
+    assert_eq!(history.entries, ["b", "c"]);
+
+    let content = fs::read_to_string(&history.path)?;
+    fs::remove_file(&history.path)?;
+    assert_eq!(content, "b\nc\n");
+    Ok(())
+  }
+
+  #[test]
+  fn moves_repeated_queries_to_the_end() -> io::Result<()> {
+    let mut history = load("repeated", 10);
+    for query in ["a", "b", "a", "", "multi\nline"] {
+      history.add(query)?;
+    }
+    fs::remove_file(&history.path)?;
+    assert_eq!(history.entries, ["b", "a"]);
+    Ok(())
+  }
+}
@@ -0,0 +1,42 @@
+/// Something that can be chosen with the selector
+pub trait SelectorItem {
+  /// The text shown for the item, in ansi mode it may contain escape sequences to colour it
+  fn display_text(&self) -> &str;
+
+  /// The text that the query is matched against, the display text by default
+  fn match_text(&self) -> &str {
+    self.display_text()
+  }
+
+  /// Whether the match text is the display text so that matches can be highlighted within it. By
+  /// default the texts are compared, implementations that override `match_text` can override
+  /// this to avoid the comparison.
+  fn highlights_display_text(&self) -> bool {
+    self.match_text() == self.display_text()
+  }
+
+  /// The text that identifies the item when it is selected, e.g. when recording frecency
+  fn output_text(&self) -> &str {
+    self.display_text()
+  }
+}
+
+impl SelectorItem for String {
+  fn display_text(&self) -> &str {
+    self
+  }
+
+  fn highlights_display_text(&self) -> bool {
+    true
+  }
+}
+
+impl SelectorItem for &str {
+  fn display_text(&self) -> &str {
+    self
+  }
+
+  fn highlights_display_text(&self) -> bool {
+    true
+  }
+}
@@ -0,0 +1,274 @@
+//! A fuzzy selector for the terminal, see [`Naru`] to embed it in another program.
+
+mod ansi;
+#[cfg(feature = "cli")]
+mod args;
+mod border;
+// the naru command, this isn't part of the library API and is left out without the cli feature
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod cli;
+mod color;
+mod config;
+mod data_file;
+mod dumb;
+mod escapes;
+mod frecency;
+mod history;
+mod item;
+mod line;
+mod render;
+#[cfg(test)]
+mod screen;
+mod selector;
+mod terminal;
+mod terminfo;
+mod tty;
+
+pub use ansi::strip_ansi;
+pub use color::Color;
+pub use config::{load_config, Config};
+pub use item::SelectorItem;
+pub use selector::Selection;
+pub use terminal::{Event, Terminal, VirtualTerminal};
+
+use frecency::Frecency;
+use history::History;
+use selector::Selector;
+use std::io;
+use tty::Tty;
+
+macro_rules! other_error {
+  ($message: expr) => {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, $message))
+  };
+
+  ($($message: expr),+) => {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, format!( $($message,)+ )))
+  };
+}
+pub(crate) use other_error;
+
+const TTY_PATH: &str = "/dev/tty";
+
+/// Interactively select from a list of choices using the terminal, e.g.
+///
+/// ```no_run
+/// let selection = naru::Naru::new(vec!["alpha", "beta"]).height(10).multi(true).run()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// The choices can be strings or any other type that implements [`SelectorItem`].
+pub struct Naru<T: SelectorItem> {
+  choices: Vec<T>,
+  header_lines: Vec<String>,
+  conf: Config,
+}
+
+impl<T: SelectorItem> Naru<T> {
+  /// A selector for the choices with the default options
+  pub fn new<I: IntoIterator<Item = T>>(choices: I) -> Naru<T> {
+    Naru {
+      choices: choices.into_iter().collect(),
+      header_lines: Vec::new(),
+      conf: Default::default(),
+    }
+  }
+
+  /// Use options from a configuration e.g. one returned by [`load_config`]
+  pub fn config(mut self, conf: Config) -> Naru<T> {
+    self.conf = conf;
+    self
+  }
+
+  /// Positive numbers are the height in lines, 0 means the full height of the terminal and the
+  /// negative number `-n` means the full height minus `n`
+  pub fn height(mut self, height: i32) -> Naru<T> {
+    self.conf.window.height = height;
+    self
+  }
+
+  /// Allow marking several choices with the toggle-mark action
+  pub fn multi(mut self, multi: bool) -> Naru<T> {
+    self.conf.multi = multi;
+    self
+  }
+
+  /// Text shown before the query
+  pub fn prompt<S: Into<String>>(mut self, prompt: S) -> Naru<T> {
+    self.conf.window.prompt = prompt.into();
+    self
+  }
+
+  /// Text shown beside the selected choice
+  pub fn pointer<S: Into<String>>(mut self, pointer: S) -> Naru<T> {
+    self.conf.window.pointer = pointer.into();
+    self
+  }
+
+  /// Text shown beside the marked choices
+  pub fn marker<S: Into<String>>(mut self, marker: S) -> Naru<T> {
+    self.conf.window.marker = marker.into();
+    self
+  }
+
+  /// Text shown above the choices
+  pub fn header<S: Into<String>>(mut self, header: S) -> Naru<T> {
+    self.conf.header = Some(header.into());
+    self
+  }
+
+  /// Lines shown above the choices after the header, these cannot be selected
+  pub fn header_lines<I, S>(mut self, header_lines: I) -> Naru<T>
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.header_lines = header_lines.into_iter().map(Into::into).collect();
+    self
+  }
+
+  /// Match against the choices with ANSI escape sequences removed and draw them with their colours
+  pub fn ansi(mut self, ansi: bool) -> Naru<T> {
+    self.conf.ansi = ansi;
+    self
+  }
+
+  /// Use bold, underline and invert instead of colours
+  pub fn no_color(mut self, no_color: bool) -> Naru<T> {
+    self.conf.no_color = no_color;
+    self
+  }
+
+  /// Keep matches in the order of the choices instead of sorting them by score
+  pub fn no_sort(mut self, no_sort: bool) -> Naru<T> {
+    self.conf.no_sort = no_sort;
+    self
+  }
+
+  /// The choices that are selected from, the indexes of a [`Selection`] refer to these
+  pub fn choices(&self) -> &[T] {
+    &self.choices
+  }
+
+  /// Select using the terminal, falling back to a numbered list when it is dumb
+  pub fn run(&self) -> io::Result<Selection<'_, T>> {
+    if dumb::is_dumb_terminal() {
+      let (idx, query) = dumb::select(TTY_PATH, &self.choices, &self.header_lines, &self.conf)?;
+      // there are no keys to accept the query or to expect in the numbered list
+      let selection = Selection {
+        key: None,
+        items: vec![&self.choices[idx]],
+        indexes: vec![idx],
+        query,
+        query_accepted: false,
+      };
+      Self::record(&selection, self.load_history()?, self.load_frecency()?)?;
+      return Ok(selection);
+    }
+
+    let mut terminal = Tty::new(TTY_PATH)?;
+    let result = self.run_on(&mut terminal);
+    terminal.reset();
+    result
+  }
+
+  /// Select using the given terminal, e.g. a [`VirtualTerminal`]
+  pub fn run_on(&self, terminal: &mut dyn Terminal) -> io::Result<Selection<'_, T>> {
+    let history = self.load_history()?;
+    let history_entries = history
+      .as_ref()
+      .map_or(&[][..], |history| &history.entries[..]);
+
+    let frecency = self.load_frecency()?;
+    let boosts: Vec<isize> = match &frecency {
+      Some(frecency) => self
+        .choices
+        .iter()
+        .map(|choice| frecency.boost(choice.output_text()))
+        .collect(),
+      None => Vec::new(),
+    };
+
+    terminal.set_no_wrap()?;
+    let result = Selector::new(
+      terminal,
+      &self.choices,
+      &self.header_lines,
+      history_entries,
+      &boosts,
+      &self.conf,
+    )
+    .get_match();
+    terminal.set_wrap()?;
+    terminal.set_col(0)?;
+    terminal.clearline()?;
+    terminal.set_normal()?;
+    terminal.flush()?;
+    let selection = result?;
+    Self::record(&selection, history, frecency)?;
+    Ok(selection)
+  }
+
+  // record the accepted query in the history and the selected choices for frecency, both ways of
+  // selecting use this so that they record the same things
+  fn record(
+    selection: &Selection<'_, T>,
+    history: Option<History>,
+    frecency: Option<Frecency>,
+  ) -> io::Result<()> {
+    if let Some(mut history) = history {
+      history.add(&selection.query)?;
+    }
+    if let Some(mut frecency) = frecency {
+      frecency.add(selection.items.iter().map(|item| item.output_text()))?;
+    }
+    Ok(())
+  }
+
+  fn load_history(&self) -> io::Result<Option<History>> {
+    if self.conf.history.enabled {
+      History::load(&self.conf.history).map(Some)
+    } else {
+      Ok(None)
+    }
+  }
+
+  fn load_frecency(&self) -> io::Result<Option<Frecency>> {
+    if self.conf.ranking.frecency {
+      Frecency::load(&self.conf.ranking).map(Some)
+    } else {
+      Ok(None)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{env, fs};
+
+  #[test]
+  fn records_the_query_and_the_selected_choices() -> io::Result<()> {
+    let dir = env::temp_dir().join(format!("naru-record-{}", std::process::id()));
+    let mut naru = Naru::new(vec!["alpha", "beta"]);
+    naru.conf.history.enabled = true;
+    naru.conf.history.file = Some(dir.join("history"));
+    naru.conf.ranking.frecency = true;
+    naru.conf.ranking.file = Some(dir.join("frecency"));
+
+    let mut terminal = VirtualTerminal::new(20, 5);
+    for key in ["b", "e", "\r"] {
+      terminal.push_key(key);
+    }
+    let selection = naru.run_on(&mut terminal)?;
+    assert_eq!(selection.indexes, [1]);
+
+    let history = fs::read_to_string(dir.join("history"))?;
+    let frecency = fs::read_to_string(dir.join("frecency"))?;
+    fs::remove_dir_all(&dir)?;
+    assert_eq!(history, "be\n");
+    assert!(frecency.ends_with("\tbeta\n"));
+    Ok(())
+  }
+}
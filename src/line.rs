@@ -0,0 +1,176 @@
+use crate::terminal::Terminal;
+use std::io;
+use unicode_width::UnicodeWidthChar;
+
+const TAB_WIDTH: usize = 8;
+const ELLIPSIS: &str = "…";
+
+enum Unit<'a> {
+  Escape(&'a str),
+  Char(char),
+}
+
+// Length in bytes of the CSI escape sequence at the start of the given text, or 0 if it does not
+// begin with a complete one
+pub(crate) fn escape_sequence_len(text: &str) -> usize {
+  let bytes = text.as_bytes();
+  if bytes.len() < 3 || bytes[0] != b'\x1b' || bytes[1] != b'[' {
+    return 0;
+  }
+
+  for (i, &byte) in bytes.iter().enumerate().skip(2) {
+    if (0x40..=0x7e).contains(&byte) {
+      return i + 1;
+    } else if !(0x20..=0x3f).contains(&byte) {
+      return 0;
+    }
+  }
+  0
+}
+
+// Split the text into escape sequences and the characters between them
+fn next_unit(text: &str) -> Option<(Unit<'_>, usize)> {
+  let escape_len = escape_sequence_len(text);
+  if escape_len != 0 {
+    return Some((Unit::Escape(&text[0..escape_len]), escape_len));
+  }
+  text.chars().next().map(|c| (Unit::Char(c), c.len_utf8()))
+}
+
+// Control characters like newlines would break the layout so they are drawn as the corresponding
+// symbol from the control pictures block instead
+fn visible_char(c: char) -> char {
+  match c {
+    '\0'..='\x1f' => std::char::from_u32(0x2400 + c as u32).unwrap_or('?'),
+    '\x7f' => '\u{2421}',
+    c if c.is_control() => '?',
+    c => c,
+  }
+}
+
+// Number of columns the character occupies when drawn at the given column
+pub(crate) fn char_width(c: char, col: usize) -> usize {
+  if c == '\t' {
+    TAB_WIDTH - col % TAB_WIDTH
+  } else if c.is_control() {
+    1
+  } else {
+    c.width().unwrap_or(0)
+  }
+}
+
+// Number of columns the text occupies on screen, escape sequences take up no space
+pub(crate) fn display_width(text: &str) -> usize {
+  let mut col = 0;
+  let mut rest = text;
+  while let Some((unit, len)) = next_unit(rest) {
+    if let Unit::Char(c) = unit {
+      col += char_width(c, col);
+    }
+    rest = &rest[len..];
+  }
+  col
+}
+
+// Prints a single line to the terminal clipped to a window of columns starting at a horizontal
+// scroll offset. When content is cut off at either side the edge column shows an ellipsis instead.
+pub(crate) struct LineWriter {
+  // column within the full line of the next character to print
+  col: usize,
+  // the range of columns within the full line where content is visible
+  start: usize,
+  end: usize,
+  truncated: bool,
+  buffer: String,
+}
+
+impl LineWriter {
+  pub fn new(
+    terminal: &mut dyn Terminal,
+    line_width: usize,
+    offset: usize,
+    width: usize,
+  ) -> io::Result<LineWriter> {
+    // never scroll further than is necessary to show the end of the line
+    let offset = std::cmp::min(offset, line_width.saturating_sub(width));
+    let truncated = line_width > offset + width;
+
+    let start = if offset > 0 {
+      terminal.print(ELLIPSIS)?;
+      offset + 1
+    } else {
+      0
+    };
+
+    let end = if truncated {
+      (offset + width).saturating_sub(1)
+    } else {
+      offset + width
+    };
+
+    Ok(LineWriter {
+      col: 0,
+      start,
+      end,
+      truncated,
+      buffer: String::new(),
+    })
+  }
+
+  pub fn print(&mut self, terminal: &mut dyn Terminal, text: &str) -> io::Result<()> {
+    let mut rest = text;
+    while let Some((unit, len)) = next_unit(rest) {
+      match unit {
+        // always forward escape sequences so that the terminal state is the same as if the
+        // entire line was printed
+        Unit::Escape(sequence) => self.buffer.push_str(sequence),
+        Unit::Char(c) => {
+          let width = char_width(c, self.col);
+          let col_end = self.col + width;
+          if width == 0 {
+            // zero width characters combine with the previous character so are only shown with it
+            if (self.col > self.start || self.col == 0) && self.col <= self.end {
+              self.buffer.push(c);
+            }
+          } else if self.col >= self.start && col_end <= self.end && c != '\t' {
+            self.buffer.push(visible_char(c));
+          } else if self.col < self.end && col_end > self.start {
+            // a tab or a wide character that is partially visible, fill the visible part with
+            // spaces to keep the following columns aligned
+            let visible = std::cmp::min(col_end, self.end) - std::cmp::max(self.col, self.start);
+            for _ in 0..visible {
+              self.buffer.push(' ');
+            }
+          }
+          self.col = col_end;
+        }
+      }
+      rest = &rest[len..];
+    }
+
+    terminal.print(&self.buffer)?;
+    self.buffer.clear();
+    Ok(())
+  }
+
+  pub fn finish(&self, terminal: &mut dyn Terminal) -> io::Result<()> {
+    if self.truncated {
+      for _ in self.col..self.end {
+        terminal.print(" ")?;
+      }
+      terminal.print(ELLIPSIS)?;
+    }
+    Ok(())
+  }
+}
+
+// The horizontal scroll offset needed to show the text between the given columns within a window
+// of the given width, leaving space for the ellipses
+pub(crate) fn scroll_offset(start_col: usize, end_col: usize, width: usize) -> usize {
+  if end_col < width {
+    0
+  } else {
+    // prefer to keep the start of the range visible when it cannot fit entirely
+    std::cmp::min(end_col + 1 - width, start_col.saturating_sub(1))
+  }
+}
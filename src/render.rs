@@ -0,0 +1,763 @@
+use crate::{
+  color::Color,
+  line,
+  terminal::{Event, Terminal},
+};
+use std::{io, time::Duration};
+use unicode_width::UnicodeWidthChar;
+
+// The colours and attributes a cell is drawn with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Style {
+  pub fg: Color,
+  pub bg: Color,
+  pub bold: bool,
+  pub underline: bool,
+  pub invert: bool,
+  // only text printed with its own escape sequences is drawn with these
+  pub dim: bool,
+  pub italic: bool,
+  pub blink: bool,
+  pub strikethrough: bool,
+}
+
+impl Default for Style {
+  fn default() -> Style {
+    Style {
+      fg: Color::Default,
+      bg: Color::Default,
+      bold: false,
+      underline: false,
+      invert: false,
+      dim: false,
+      italic: false,
+      blink: false,
+      strikethrough: false,
+    }
+  }
+}
+
+impl Style {
+  // Apply the parameters of an SGR escape sequence i.e. the part between "\x1b[" and "m".
+  // Parameters may have sub-parameters separated by colons e.g. "4:3" for a curly underline, those
+  // that aren't understood are ignored.
+  pub fn apply_sgr(&mut self, params: &str) {
+    let mut params = params.split(';');
+    while let Some(param) = params.next() {
+      let mut sub_params = param.split(':');
+      let number: u32 = match sub_params.next().unwrap_or("") {
+        "" => 0,
+        number => match number.parse() {
+          Ok(number) => number,
+          Err(_) => continue,
+        },
+      };
+      match number {
+        0 => *self = Style::default(),
+        1 => self.bold = true,
+        2 => self.dim = true,
+        3 => self.italic = true,
+        // the styles of underline are all drawn as a plain one, 4:0 turns it off
+        4 => self.underline = sub_params.next() != Some("0"),
+        5 | 6 => self.blink = true,
+        7 => self.invert = true,
+        9 => self.strikethrough = true,
+        21 => self.underline = true,
+        22 => {
+          self.bold = false;
+          self.dim = false;
+        }
+        23 => self.italic = false,
+        24 => self.underline = false,
+        25 => self.blink = false,
+        27 => self.invert = false,
+        29 => self.strikethrough = false,
+        30..=37 => self.fg = Color::Ansi(number as u8 - 30),
+        39 => self.fg = Color::Default,
+        40..=47 => self.bg = Color::Ansi(number as u8 - 40),
+        49 => self.bg = Color::Default,
+        90..=97 => self.fg = Color::Ansi(number as u8 - 90 + 8),
+        100..=107 => self.bg = Color::Ansi(number as u8 - 100 + 8),
+        38 | 48 => {
+          let color = if param.contains(':') {
+            let values: Vec<u32> = sub_params.map(|value| value.parse().unwrap_or(0)).collect();
+            match values.split_first() {
+              Some((5, [idx, ..])) => Color::Indexed(*idx as u8),
+              // the colour space before the components is optional
+              Some((2, [.., r, g, b])) => Color::Rgb(*r as u8, *g as u8, *b as u8),
+              _ => Color::Default,
+            }
+          } else {
+            let mut next = || {
+              params
+                .next()
+                .and_then(|value| value.parse::<u32>().ok())
+                .unwrap_or(0)
+            };
+            match next() {
+              5 => Color::Indexed(next() as u8),
+              2 => Color::Rgb(next() as u8, next() as u8, next() as u8),
+              _ => Color::Default,
+            }
+          };
+          if number == 38 {
+            self.fg = color;
+          } else {
+            self.bg = color;
+          }
+        }
+        _ => {}
+      }
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Cell {
+  // the character drawn in the cell, the second cell of a wide character is empty
+  pub text: String,
+  pub style: Style,
+}
+
+impl Cell {
+  pub fn blank(style: Style) -> Cell {
+    Cell {
+      text: " ".to_string(),
+      style,
+    }
+  }
+
+  fn is_blank(&self) -> bool {
+    self.text == " " && self.style == Style::default()
+  }
+}
+
+// Put a character into a row of cells, returning the column after it. Wide characters that don't
+// fit are dropped and zero width characters combine with the previous character.
+pub(crate) fn put_char(cells: &mut [Cell], col: usize, c: char, style: Style) -> usize {
+  let width = c.width().unwrap_or(0);
+  if width == 0 {
+    if col > 0 {
+      cells[col - 1].text.push(c);
+    }
+    return col;
+  }
+  if col + width > cells.len() {
+    return col;
+  }
+
+  cells[col] = Cell {
+    text: c.to_string(),
+    style,
+  };
+  for cell in &mut cells[col + 1..col + width] {
+    *cell = Cell {
+      text: String::new(),
+      style,
+    };
+  }
+  col + width
+}
+
+// A screen of cells with a cursor that behaves like a terminal. Text wraps onto the next row when
+// wrapping is on and overwrites the last column when it's off, and a line feed on the last row
+// scrolls. This is the screen of the terminals that draw to memory.
+pub(crate) struct CellGrid {
+  pub width: usize,
+  pub height: usize,
+  pub cells: Vec<Vec<Cell>>,
+  pub row: usize,
+  pub col: usize,
+  pub wrap: bool,
+}
+
+impl CellGrid {
+  pub fn new(width: usize, height: usize) -> CellGrid {
+    CellGrid {
+      width,
+      height,
+      cells: vec![vec![Cell::blank(Style::default()); width]; height],
+      row: 0,
+      col: 0,
+      wrap: true,
+    }
+  }
+
+  pub fn resize(&mut self, width: usize, height: usize) {
+    for row in &mut self.cells {
+      row.resize(width, Cell::blank(Style::default()));
+    }
+    self
+      .cells
+      .resize(height, vec![Cell::blank(Style::default()); width]);
+    self.width = width;
+    self.height = height;
+    self.row = std::cmp::min(self.row, height.saturating_sub(1));
+    self.col = std::cmp::min(self.col, width.saturating_sub(1));
+  }
+
+  // the text of a row with trailing spaces removed
+  pub fn line(&self, row: usize) -> String {
+    let text: String = self.cells[row]
+      .iter()
+      .map(|cell| cell.text.as_str())
+      .collect();
+    text.trim_end().to_string()
+  }
+
+  pub fn put_char(&mut self, c: char, style: Style) {
+    let width = c.width().unwrap_or(0);
+    if width > 0 && self.col + width > self.width {
+      if self.wrap {
+        self.col = 0;
+        self.line_feed();
+      } else {
+        self.col = self.width.saturating_sub(width);
+      }
+    }
+    self.col = put_char(&mut self.cells[self.row], self.col, c, style);
+  }
+
+  pub fn line_feed(&mut self) {
+    if self.row + 1 < self.height {
+      self.row += 1;
+    } else {
+      self.cells.remove(0);
+      self
+        .cells
+        .push(vec![Cell::blank(Style::default()); self.width]);
+    }
+  }
+
+  // replace the cells from the column to the end of the row with blanks of the style
+  pub fn clear_cells(&mut self, row: usize, start_col: usize, style: Style) {
+    for cell in self.cells[row].iter_mut().skip(start_col) {
+      *cell = Cell::blank(style);
+    }
+  }
+}
+
+fn is_blank_row(row: &[Cell]) -> bool {
+  row.iter().all(Cell::is_blank)
+}
+
+// Draws the window to the terminal by writing only what changed since the previous frame. The
+// drawing operations are applied to a frame buffer holding the rows of the window and the changes
+// are written to the terminal when the frame is flushed, wrapped in a synchronized update when it
+// is enabled so that terminals supporting it show the frame at once.
+pub(crate) struct Renderer<'a> {
+  terminal: &'a mut dyn Terminal,
+  synchronized: bool,
+  // the frame being drawn, row 0 is the row the window starts on
+  rows: Vec<Vec<Cell>>,
+  // the frame as it was written to the terminal, None when the contents of the terminal are
+  // unknown e.g. before the first frame and after a resize
+  previous: Option<Vec<Vec<Cell>>>,
+  row: usize,
+  col: usize,
+  style: Style,
+
+  // position of the terminal's cursor within the window, the column is None when unknown
+  terminal_row: usize,
+  terminal_col: Option<usize>,
+  terminal_style: Style,
+  // number of rows of the window that the terminal's cursor has moved into, moving below them
+  // needs line feeds which scroll the terminal when the window is at the bottom of it
+  created_rows: usize,
+}
+
+impl<'a> Renderer<'a> {
+  pub fn new(terminal: &'a mut dyn Terminal, synchronized: bool) -> Renderer<'a> {
+    let width = terminal.width();
+    Renderer {
+      terminal,
+      synchronized,
+      rows: vec![vec![Cell::blank(Style::default()); width]],
+      previous: None,
+      row: 0,
+      col: 0,
+      style: Style::default(),
+      terminal_row: 0,
+      terminal_col: None,
+      terminal_style: Style::default(),
+      created_rows: 1,
+    }
+  }
+
+  fn blank_row(&self) -> Vec<Cell> {
+    vec![Cell::blank(Style::default()); self.terminal.width()]
+  }
+
+  // erased cells take the current background colour like they do in most terminals
+  fn clear_cells(&mut self, start_col: usize) {
+    let blank = Cell::blank(Style {
+      bg: self.style.bg,
+      ..Style::default()
+    });
+    for cell in self.rows[self.row].iter_mut().skip(start_col) {
+      *cell = blank.clone();
+    }
+  }
+
+  fn move_to_row(&mut self, row: usize) {
+    self.row = row;
+    while self.rows.len() <= row {
+      let blank_row = self.blank_row();
+      self.rows.push(blank_row);
+    }
+  }
+
+  // The columns of a row that differ from the previous frame, the whole row when the previous
+  // frame is unknown
+  fn changed_cols(&self, row: usize) -> Option<(usize, usize)> {
+    let cells = &self.rows[row];
+    let previous = match &self.previous {
+      Some(previous) => previous.get(row),
+      None => return Some((0, cells.len())),
+    };
+    let differs = |col: &usize| match previous.and_then(|previous| previous.get(*col)) {
+      Some(previous_cell) => *previous_cell != cells[*col],
+      None => !cells[*col].is_blank(),
+    };
+
+    let start = (0..cells.len()).find(differs)?;
+    let end = (0..cells.len()).rev().find(differs).unwrap() + 1;
+    Some((start, end))
+  }
+
+  fn write_style(&mut self, style: Style) -> io::Result<()> {
+    if style == self.terminal_style {
+      return Ok(());
+    }
+    if self.terminal_style != Style::default() {
+      self.terminal.set_normal()?;
+    }
+    if style.fg != Color::Default {
+      self.terminal.set_fg(style.fg)?;
+    }
+    if style.bg != Color::Default {
+      self.terminal.set_bg(style.bg)?;
+    }
+    if style.bold {
+      self.terminal.set_bold()?;
+    }
+    if style.underline {
+      self.terminal.set_underline()?;
+    }
+    if style.invert {
+      self.terminal.set_invert()?;
+    }
+    // the terminal has no operations for the attributes of printed text so their SGR sequences are
+    // printed instead
+    let printed_attributes = [
+      (style.dim, "\x1b[2m"),
+      (style.italic, "\x1b[3m"),
+      (style.blink, "\x1b[5m"),
+      (style.strikethrough, "\x1b[9m"),
+    ];
+    for (enabled, sequence) in printed_attributes {
+      if enabled {
+        self.terminal.print(sequence)?;
+      }
+    }
+    self.terminal_style = style;
+    Ok(())
+  }
+
+  fn write_cursor_row(&mut self, row: usize) -> io::Result<()> {
+    if row < self.terminal_row {
+      self.terminal.move_up(self.terminal_row - row)?;
+      self.terminal_row = row;
+    }
+
+    let last_created_row = self.created_rows - 1;
+    if row > self.terminal_row && self.terminal_row < last_created_row {
+      let down = std::cmp::min(row, last_created_row) - self.terminal_row;
+      self.terminal.move_down(down)?;
+      self.terminal_row += down;
+    }
+    while self.terminal_row < row {
+      // a line feed creates the row, which returns to the first column as the tty translates it.
+      // The row is cleared as it may hold output from before the window was drawn.
+      self.write_style(Style::default())?;
+      self.terminal.print("\n")?;
+      self.terminal.clearline()?;
+      self.terminal_row += 1;
+      self.terminal_col = Some(0);
+    }
+    self.created_rows = std::cmp::max(self.created_rows, self.terminal_row + 1);
+    Ok(())
+  }
+
+  fn write_cursor_col(&mut self, col: usize) -> io::Result<()> {
+    if self.terminal_col != Some(col) {
+      self.terminal.set_col(col)?;
+      self.terminal_col = Some(col);
+    }
+    Ok(())
+  }
+
+  // write the cells between the columns of a row, clearing instead of writing trailing blanks
+  fn write_cells(&mut self, row: usize, start: usize, end: usize) -> io::Result<()> {
+    let cells = &self.rows[row];
+    // include the whole of wide characters at either side
+    let mut start = start;
+    while start > 0 && cells[start].text.is_empty() {
+      start -= 1;
+    }
+    let mut end = end;
+    while end < cells.len() && cells[end].text.is_empty() {
+      end += 1;
+    }
+    let content_end = cells
+      .iter()
+      .rposition(|cell| !cell.is_blank())
+      .map_or(0, |col| col + 1);
+
+    self.write_cursor_row(row)?;
+    self.write_cursor_col(start)?;
+    for col in start..std::cmp::min(end, content_end) {
+      let cell = &self.rows[row][col];
+      if cell.text.is_empty() {
+        continue;
+      }
+      let (style, text) = (cell.style, cell.text.clone());
+      self.write_style(style)?;
+      self.terminal.print(&text)?;
+    }
+    self.terminal_col = None;
+
+    if end > content_end {
+      self.write_style(Style::default())?;
+      self.terminal.clearline()?;
+    }
+    Ok(())
+  }
+}
+
+impl Terminal for Renderer<'_> {
+  fn width(&self) -> usize {
+    self.terminal.width()
+  }
+
+  fn height(&self) -> usize {
+    self.terminal.height()
+  }
+
+  // the terminal may have reflowed the window so the next frame is drawn in full
+  fn update_size(&mut self) -> io::Result<()> {
+    self.terminal.update_size()?;
+    let width = self.terminal.width();
+    for row in &mut self.rows {
+      row.resize(width, Cell::blank(Style::default()));
+    }
+    self.col = std::cmp::min(self.col, width.saturating_sub(1));
+    self.previous = None;
+    self.terminal_col = None;
+    Ok(())
+  }
+
+  fn read_event(&mut self) -> io::Result<Event> {
+    self.terminal.read_event()
+  }
+
+  fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+    self.terminal.poll_event(timeout)
+  }
+
+  // SGR sequences within the text change the style, other escape sequences are dropped
+  fn print(&mut self, text: &str) -> io::Result<()> {
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+      let escape_len = line::escape_sequence_len(rest);
+      if escape_len != 0 {
+        if rest[..escape_len].ends_with('m') {
+          self.style.apply_sgr(&rest[2..escape_len - 1]);
+        }
+        rest = &rest[escape_len..];
+        continue;
+      }
+
+      match c {
+        '\r' => self.col = 0,
+        '\n' => {
+          self.col = 0;
+          self.move_to_row(self.row + 1);
+        }
+        c if c.is_control() => {}
+        c => {
+          let style = self.style;
+          self.col = put_char(&mut self.rows[self.row], self.col, c, style);
+        }
+      }
+      rest = &rest[c.len_utf8()..];
+    }
+    Ok(())
+  }
+
+  fn clearline(&mut self) -> io::Result<()> {
+    self.clear_cells(self.col);
+    Ok(())
+  }
+
+  fn clear_screen_below(&mut self) -> io::Result<()> {
+    self.clear_cells(self.col);
+    self.rows.truncate(self.row + 1);
+    Ok(())
+  }
+
+  fn newline(&mut self) -> io::Result<()> {
+    self.clear_cells(self.col);
+    self.col = 0;
+    self.move_to_row(self.row + 1);
+    Ok(())
+  }
+
+  fn set_col(&mut self, col: usize) -> io::Result<()> {
+    self.col = std::cmp::min(col, self.terminal.width().saturating_sub(1));
+    Ok(())
+  }
+
+  fn move_up(&mut self, row_count: usize) -> io::Result<()> {
+    self.row = self.row.saturating_sub(row_count);
+    Ok(())
+  }
+
+  fn move_down(&mut self, row_count: usize) -> io::Result<()> {
+    self.move_to_row(self.row + row_count);
+    Ok(())
+  }
+
+  fn set_no_wrap(&mut self) -> io::Result<()> {
+    self.terminal.set_no_wrap()
+  }
+
+  fn set_wrap(&mut self) -> io::Result<()> {
+    self.terminal.set_wrap()
+  }
+
+  fn set_fg(&mut self, color: Color) -> io::Result<()> {
+    self.style.fg = color;
+    Ok(())
+  }
+
+  fn set_bg(&mut self, color: Color) -> io::Result<()> {
+    self.style.bg = color;
+    Ok(())
+  }
+
+  fn set_bold(&mut self) -> io::Result<()> {
+    self.style.bold = true;
+    Ok(())
+  }
+
+  fn set_underline(&mut self) -> io::Result<()> {
+    self.style.underline = true;
+    Ok(())
+  }
+
+  fn set_invert(&mut self) -> io::Result<()> {
+    self.style.invert = true;
+    Ok(())
+  }
+
+  fn set_normal(&mut self) -> io::Result<()> {
+    self.style = Style::default();
+    Ok(())
+  }
+
+  // write the changes since the previous frame
+  fn flush(&mut self) -> io::Result<()> {
+    let unchanged = self.previous.as_ref() == Some(&self.rows)
+      && self.terminal_row == self.row
+      && self.terminal_col == Some(self.col);
+    if unchanged {
+      return self.terminal.flush();
+    }
+    if self.synchronized {
+      self.terminal.begin_synchronized_update()?;
+    }
+
+    // rows from blank_start down are blank so they can be cleared at once
+    let blank_start = self
+      .rows
+      .iter()
+      .rposition(|row| !is_blank_row(row))
+      .map_or(0, |row| row + 1);
+    for row in 0..blank_start {
+      if let Some((start, end)) = self.changed_cols(row) {
+        self.write_cells(row, start, end)?;
+      }
+    }
+
+    // only the rows that have been created can hold anything that was drawn
+    let below_changed = match &self.previous {
+      Some(previous) => previous
+        .iter()
+        .skip(blank_start)
+        .any(|row| !is_blank_row(row)),
+      None => true,
+    };
+    if below_changed && blank_start < self.created_rows {
+      self.write_cursor_row(blank_start)?;
+      self.write_cursor_col(0)?;
+      self.write_style(Style::default())?;
+      self.terminal.clear_screen_below()?;
+    }
+
+    // every row of the frame is created even when blank so that the window keeps its height
+    if self.created_rows < self.rows.len() {
+      self.write_cursor_row(self.rows.len() - 1)?;
+    }
+    self.write_style(Style::default())?;
+    self.write_cursor_row(self.row)?;
+    self.write_cursor_col(self.col)?;
+
+    if self.synchronized {
+      self.terminal.end_synchronized_update()?;
+    }
+    self.terminal.flush()?;
+    self.previous = Some(self.rows.clone());
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::screen::Screen;
+
+  // the output of the last frame drawn to the screen
+  fn last_frame(screen: &mut Screen) -> String {
+    let output = screen.take_output();
+    output.rsplit("\x1b[?2026h").next().unwrap().to_string()
+  }
+
+  #[test]
+  fn writes_the_cells_that_changed() -> io::Result<()> {
+    let mut screen = Screen::new(10, 3);
+    let mut renderer = Renderer::new(&mut screen, true);
+    renderer.print("abcdef")?;
+    renderer.newline()?;
+    renderer.print("ghi")?;
+    renderer.flush()?;
+
+    renderer.move_up(1)?;
+    renderer.set_col(0)?;
+    renderer.print("abXdef")?;
+    renderer.flush()?;
+
+    assert_eq!(screen.lines(), ["abXdef", "ghi"]);
+    // enough for the changed cell and a few cursor movements but not for the rows
+    assert!(last_frame(&mut screen).len() <= 24);
+    Ok(())
+  }
+
+  #[test]
+  fn writes_changes_of_style() -> io::Result<()> {
+    let mut screen = Screen::new(10, 3);
+    let mut renderer = Renderer::new(&mut screen, true);
+    renderer.print("abc")?;
+    renderer.flush()?;
+
+    renderer.set_col(1)?;
+    renderer.print("\x1b[1mb")?;
+    renderer.set_normal()?;
+    renderer.flush()?;
+
+    assert_eq!(screen.lines(), ["abc"]);
+    assert_eq!(screen.marks(0, |style| style.bold), " ^");
+    // the unchanged cells aren't written again with the new style
+    assert!(last_frame(&mut screen).len() <= 28);
+    Ok(())
+  }
+
+  #[test]
+  fn keeps_the_attributes_of_printed_text() -> io::Result<()> {
+    let mut screen = Screen::new(10, 3);
+    let mut renderer = Renderer::new(&mut screen, true);
+    renderer.print("\x1b[2;3ma\x1b[0;5;9mb\x1b[0;1mc\x1b[4:3md")?;
+    renderer.flush()?;
+
+    assert_eq!(screen.lines(), ["abcd"]);
+    assert_eq!(screen.marks(0, |style| style.dim && style.italic), "^");
+    assert_eq!(
+      screen.marks(0, |style| style.blink && style.strikethrough),
+      " ^"
+    );
+    // the sub-parameters of the curly underline don't reset the bold text before it
+    assert_eq!(screen.marks(0, |style| style.bold), "  ^^");
+    assert_eq!(screen.marks(0, |style| style.underline), "   ^");
+    Ok(())
+  }
+
+  #[test]
+  fn applies_sgr_sub_parameters() {
+    let mut style = Style::default();
+    style.apply_sgr("1;4:3;38:2::255:128:0;48:5:236");
+    assert_eq!(
+      style,
+      Style {
+        fg: Color::Rgb(255, 128, 0),
+        bg: Color::Indexed(236),
+        bold: true,
+        underline: true,
+        ..Style::default()
+      }
+    );
+
+    style.apply_sgr("4:0;x;38:2:1:2:3");
+    assert!(style.bold && !style.underline);
+    assert_eq!(style.fg, Color::Rgb(1, 2, 3));
+  }
+
+  #[test]
+  fn clears_rows_that_are_no_longer_drawn() -> io::Result<()> {
+    let mut screen = Screen::new(10, 4);
+    let mut renderer = Renderer::new(&mut screen, true);
+    renderer.print("abc")?;
+    renderer.newline()?;
+    renderer.print("def")?;
+    renderer.newline()?;
+    renderer.print("ghi")?;
+    renderer.move_up(2)?;
+    renderer.flush()?;
+
+    renderer.set_col(0)?;
+    renderer.print("ab")?;
+    renderer.clear_screen_below()?;
+    renderer.flush()?;
+
+    assert_eq!(screen.lines(), ["ab"]);
+    assert_eq!(screen.cursor(), (0, 2));
+    // the rows below are cleared at once instead of being overwritten with spaces
+    assert!(last_frame(&mut screen).len() <= 36);
+    Ok(())
+  }
+
+  #[test]
+  fn does_not_write_unchanged_frames() -> io::Result<()> {
+    let mut screen = Screen::new(10, 3);
+    let mut renderer = Renderer::new(&mut screen, true);
+    renderer.print("abc")?;
+    renderer.flush()?;
+    renderer.set_col(0)?;
+    renderer.print("abc")?;
+    renderer.flush()?;
+
+    assert_eq!(screen.take_output().matches("\x1b[?2026h").count(), 1);
+    Ok(())
+  }
+
+  #[test]
+  fn writes_frames_without_synchronized_updates_when_disabled() -> io::Result<()> {
+    let mut screen = Screen::new(10, 3);
+    let mut renderer = Renderer::new(&mut screen, false);
+    renderer.print("abc")?;
+    renderer.flush()?;
+
+    assert_eq!(screen.lines(), ["abc"]);
+    assert!(!screen.take_output().contains("\x1b[?2026"));
+    Ok(())
+  }
+}
@@ -0,0 +1,337 @@
+use crate::{
+  color::Color,
+  escapes::EscapeWriter,
+  other_error,
+  render::{CellGrid, Style},
+  terminal::{Event, Terminal},
+};
+use std::{
+  collections::VecDeque,
+  io::{self, Write},
+  time::Duration,
+};
+
+// A terminal emulator for tests. Drawing goes through the same EscapeWriter as Tty and the escape
+// sequences it writes are interpreted into a grid of cells, so the tests check what a real
+// terminal would show. Keys are read from a queue of batches, the events within a batch arrive
+// together so are applied before drawing.
+pub(crate) struct Screen {
+  output: EscapeWriter<Grid>,
+  batches: VecDeque<VecDeque<Event>>,
+  // the size of the screen after each queued resize event
+  sizes: VecDeque<(usize, usize)>,
+}
+
+impl Screen {
+  pub fn new(width: usize, height: usize) -> Screen {
+    Screen {
+      output: EscapeWriter::new(Grid::new(width, height)),
+      batches: VecDeque::new(),
+      sizes: VecDeque::new(),
+    }
+  }
+
+  // queue a batch of keys
+  pub fn push_keys(&mut self, keys: &[&str]) {
+    let batch = keys.iter().map(|key| Event::Key(key.to_string()));
+    self.batches.push_back(batch.collect());
+  }
+
+  // queue a resize event, the size of the screen changes when the event is read
+  pub fn resize(&mut self, width: usize, height: usize) {
+    self.sizes.push_back((width, height));
+    self.batches.push_back(VecDeque::from([Event::Resize]));
+  }
+
+  fn arrive(&mut self, event: Event) -> Event {
+    if event == Event::Resize {
+      if let Some((width, height)) = self.sizes.pop_front() {
+        self.output.get_mut().cells.resize(width, height);
+      }
+    }
+    event
+  }
+
+  // (row, column) of the cursor
+  pub fn cursor(&self) -> (usize, usize) {
+    let cells = &self.output.get_ref().cells;
+    (cells.row, cells.col)
+  }
+
+  // the text of a row with trailing spaces removed
+  pub fn line(&self, row: usize) -> String {
+    self.output.get_ref().cells.line(row)
+  }
+
+  // the text of every row with trailing empty rows removed
+  pub fn lines(&self) -> Vec<String> {
+    let height = self.output.get_ref().cells.height;
+    let mut lines: Vec<String> = (0..height).map(|row| self.line(row)).collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+      lines.pop();
+    }
+    lines
+  }
+
+  // the text written to the terminal since this was last called
+  pub fn take_output(&mut self) -> String {
+    let written = std::mem::take(&mut self.output.get_mut().written);
+    String::from_utf8(written).unwrap()
+  }
+
+  pub fn is_synchronized(&self) -> bool {
+    self.output.get_ref().synchronized
+  }
+
+  pub fn style(&self, row: usize, col: usize) -> Style {
+    self.output.get_ref().cells.cells[row][col].style
+  }
+
+  // A row with '^' under the cells whose style passes the test and spaces elsewhere, trailing
+  // spaces are removed. This makes assertions about highlighting readable.
+  pub fn marks<F: Fn(&Style) -> bool>(&self, row: usize, test: F) -> String {
+    let marks: String = self.output.get_ref().cells.cells[row]
+      .iter()
+      .map(|cell| if test(&cell.style) { '^' } else { ' ' })
+      .collect();
+    marks.trim_end().to_string()
+  }
+}
+
+// The cells of the screen and the escape sequences being interpreted. A newline returns to the
+// first column as it does on a tty with ONLCR.
+struct Grid {
+  cells: CellGrid,
+  // inside a synchronized update
+  synchronized: bool,
+  style: Style,
+  // bytes of an escape sequence or character that was cut off at the end of the last write
+  pending: Vec<u8>,
+  // everything written since the output was last taken
+  written: Vec<u8>,
+}
+
+impl Grid {
+  fn new(width: usize, height: usize) -> Grid {
+    Grid {
+      cells: CellGrid::new(width, height),
+      synchronized: false,
+      style: Style::default(),
+      pending: Vec::new(),
+      written: Vec::new(),
+    }
+  }
+
+  // interpret the complete characters and escape sequences, returning the length of those that
+  // were handled
+  fn interpret(&mut self, text: &str) -> usize {
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+      match c {
+        '\x1b' => match chars.next() {
+          Some((_, '[')) => {
+            let mut params = String::new();
+            let mut complete = false;
+            for (_, c) in chars.by_ref() {
+              if ('\x40'..='\x7e').contains(&c) {
+                self.csi(&params, c);
+                complete = true;
+                break;
+              }
+              params.push(c);
+            }
+            if !complete {
+              return start;
+            }
+          }
+          Some((_, ']')) => {
+            // operating system commands like hyperlinks don't change the grid
+            let mut complete = false;
+            while let Some((_, c)) = chars.next() {
+              if c == '\x07' || (c == '\x1b' && chars.next_if(|&(_, c)| c == '\\').is_some()) {
+                complete = true;
+                break;
+              }
+            }
+            if !complete {
+              return start;
+            }
+          }
+          Some(_) => {}
+          None => return start,
+        },
+        '\r' => self.cells.col = 0,
+        '\n' => {
+          self.cells.col = 0;
+          self.cells.line_feed();
+        }
+        c if c.is_control() => {}
+        c => self.cells.put_char(c, self.style),
+      }
+    }
+    text.len()
+  }
+
+  fn csi(&mut self, params: &str, command: char) {
+    let numbers: Vec<usize> = params
+      .trim_start_matches('?')
+      .split(';')
+      .map(|param| param.parse().unwrap_or(0))
+      .collect();
+    // a missing or 0 count is treated as 1
+    let count = std::cmp::max(numbers[0], 1);
+
+    match command {
+      'm' => self.style.apply_sgr(params),
+      'K' => self.clear_cells(self.cells.row, self.cells.col),
+      'J' => {
+        self.clear_cells(self.cells.row, self.cells.col);
+        for row in self.cells.row + 1..self.cells.height {
+          self.clear_cells(row, 0);
+        }
+      }
+      'G' => self.cells.col = std::cmp::min(count - 1, self.cells.width.saturating_sub(1)),
+      'A' => self.cells.row = self.cells.row.saturating_sub(count),
+      'B' => {
+        self.cells.row = std::cmp::min(self.cells.row + count, self.cells.height.saturating_sub(1))
+      }
+      'h' | 'l' if params == "?7" => self.cells.wrap = command == 'h',
+      'h' | 'l' if params == "?2026" => self.synchronized = command == 'h',
+      _ => panic!(
+        "unexpected escape sequence {:?}",
+        format!("{}{}", params, command)
+      ),
+    }
+  }
+
+  // erased cells take the current background colour like they do in most terminals
+  fn clear_cells(&mut self, row: usize, start_col: usize) {
+    let blank = Style {
+      bg: self.style.bg,
+      ..Style::default()
+    };
+    self.cells.clear_cells(row, start_col, blank);
+  }
+}
+
+impl Write for Grid {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.written.extend_from_slice(buf);
+    self.pending.extend_from_slice(buf);
+    let pending = std::mem::take(&mut self.pending);
+    let valid_len = match std::str::from_utf8(&pending) {
+      Ok(text) => text.len(),
+      Err(error) => error.valid_up_to(),
+    };
+    let text = std::str::from_utf8(&pending[..valid_len]).unwrap();
+    let handled_len = self.interpret(text);
+    self.pending = pending[handled_len..].to_vec();
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+impl Terminal for Screen {
+  fn width(&self) -> usize {
+    self.output.get_ref().cells.width
+  }
+
+  fn height(&self) -> usize {
+    self.output.get_ref().cells.height
+  }
+
+  fn update_size(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+
+  fn read_event(&mut self) -> io::Result<Event> {
+    while let Some(batch) = self.batches.front_mut() {
+      if let Some(event) = batch.pop_front() {
+        return Ok(self.arrive(event));
+      }
+      self.batches.pop_front();
+    }
+    other_error!("No more input")
+  }
+
+  // only events from the current batch have arrived
+  fn poll_event(&mut self, _timeout: Duration) -> io::Result<Option<Event>> {
+    let event = self.batches.front_mut().and_then(VecDeque::pop_front);
+    Ok(event.map(|event| self.arrive(event)))
+  }
+
+  fn print(&mut self, text: &str) -> io::Result<()> {
+    self.output.print(text)
+  }
+
+  fn clearline(&mut self) -> io::Result<()> {
+    self.output.clearline()
+  }
+
+  fn clear_screen_below(&mut self) -> io::Result<()> {
+    self.output.clear_screen_below()
+  }
+
+  fn newline(&mut self) -> io::Result<()> {
+    self.output.newline()
+  }
+
+  fn set_col(&mut self, col: usize) -> io::Result<()> {
+    self.output.set_col(col)
+  }
+
+  fn move_up(&mut self, row_count: usize) -> io::Result<()> {
+    self.output.move_up(row_count)
+  }
+
+  fn move_down(&mut self, row_count: usize) -> io::Result<()> {
+    self.output.move_down(row_count)
+  }
+
+  fn set_no_wrap(&mut self) -> io::Result<()> {
+    self.output.set_no_wrap()
+  }
+
+  fn set_wrap(&mut self) -> io::Result<()> {
+    self.output.set_wrap()
+  }
+
+  fn set_fg(&mut self, color: Color) -> io::Result<()> {
+    self.output.set_fg(color)
+  }
+
+  fn set_bg(&mut self, color: Color) -> io::Result<()> {
+    self.output.set_bg(color)
+  }
+
+  fn set_bold(&mut self) -> io::Result<()> {
+    self.output.set_bold()
+  }
+
+  fn set_underline(&mut self) -> io::Result<()> {
+    self.output.set_underline()
+  }
+
+  fn set_invert(&mut self) -> io::Result<()> {
+    self.output.set_invert()
+  }
+
+  fn set_normal(&mut self) -> io::Result<()> {
+    self.output.set_normal()
+  }
+
+  fn begin_synchronized_update(&mut self) -> io::Result<()> {
+    self.output.begin_synchronized_update()
+  }
+
+  fn end_synchronized_update(&mut self) -> io::Result<()> {
+    self.output.end_synchronized_update()
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.output.flush()
+  }
+}
@@ -1,7 +1,23 @@
-use crate::{config::Config, control_key, other_error, tty, tty::Tty};
+use crate::{
+  ansi::{AnsiString, SgrState},
+  border::Frame,
+  color::Color,
+  config::{Config, InfoStyle, Tiebreak},
+  item::SelectorItem,
+  line::{self, LineWriter},
+  other_error,
+  render::Renderer,
+  terminal::{Event, Terminal},
+  tty::{self, control_key},
+};
 use sublime_fuzzy::best_match;
 
-use std::{collections::HashMap, ffi::CStr, io};
+use std::{
+  cmp::Ordering,
+  collections::{BTreeSet, HashMap},
+  io,
+  time::{Duration, Instant},
+};
 
 macro_rules! def_action_names {
   ($actions_by_name: ident, $($name: expr => $mapping: ident);+;) => {
@@ -11,6 +27,13 @@ macro_rules! def_action_names {
   };
 }
 
+type Action<S> = fn(&mut S) -> io::Result<()>;
+
+const MATCH_CHUNK_SIZE: usize = 100_000;
+// how long matching can take before a spinner is shown
+const SPINNER_DELAY: Duration = Duration::from_millis(100);
+const SPINNER_FRAMES: &[char] = &['-', '\\', '|', '/'];
+
 macro_rules! def_default_mappings {
   ($actions: ident, $($name: expr => $mapping: ident);+;) => {{
     $(
@@ -19,275 +42,920 @@ macro_rules! def_default_mappings {
   }};
 }
 
-struct Match<'a> {
+// sublime_fuzzy reports ranges as (start, length) in characters, convert these to bytes so that
+// they can be used to slice the choice
+fn char_ranges_to_byte_ranges(choice: &str, ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+  let mut char_offsets = choice.char_indices().map(|(offset, _)| offset);
+  let mut char_idx = 0;
+  let mut byte_offset = |target: usize| {
+    let offset = char_offsets.nth(target - char_idx).unwrap_or(choice.len());
+    char_idx = target + 1;
+    offset
+  };
+
+  ranges
+    .into_iter()
+    .map(|(start, len)| {
+      let start_byte = byte_offset(start);
+      let end_byte = byte_offset(start + len);
+      (start_byte, end_byte - start_byte)
+    })
+    .collect()
+}
+
+struct Match {
+  // index of the matched choice
+  idx: usize,
   // match ranges
-  choice: &'a String,
   ranges: Vec<(usize, usize)>,
   score: isize,
 }
 
-pub(crate) struct Selector<'a, 'b> {
+/// The result of a selection
+pub struct Selection<'a, T> {
+  /// Name of the expected key that accepted the selection, None when it was accepted with enter
+  pub key: Option<String>,
+  /// The marked choices or the selected choice when none are marked
+  pub items: Vec<&'a T>,
+  /// Indexes of the items within the choices
+  pub indexes: Vec<usize>,
+  /// The criteria that were entered
+  pub query: String,
+  /// Whether the query was accepted instead of the choices, which are empty in that case
+  pub query_accepted: bool,
+}
+
+pub(crate) struct Selector<'a, 'b, T: SelectorItem> {
   // inputs
-  terminal: &'a mut Tty,
-  choices: &'b Vec<String>,
-  matches: Vec<Match<'b>>,
-  conf: &'b Config,
+  terminal: Renderer<'a>,
+  choices: &'b [T],
+  matches: Vec<Match>,
+  conf: &'a Config,
+  // amounts added to the scores of matches for each choice, empty when no choices are boosted
+  boosts: &'a [isize],
+  // choices with their escape sequences separated out, only used in ansi mode
+  ansi_choices: Vec<AnsiString>,
 
   // min of terminal height or config height
   height: usize,
   selected: usize,
   criteria: String,
+  // indexes of the choices that have been marked for output, in input order
+  marked: BTreeSet<usize>,
+  // set by the accept-query action to finish with the criteria instead of the selected choices
+  query_accepted: bool,
+  // name of the expected key that finished the selection
+  key: Option<String>,
+  // set when the selection is finished
+  done: bool,
+  // the criteria have changed since they were last matched
+  needs_match: bool,
+  // something has changed since the window was last drawn
+  needs_redraw: bool,
+  // lines shown above the options that cannot be selected
+  header: Vec<&'a str>,
+  // margin, border and padding around the contents
+  frame: Frame,
+  // queries from previous runs, the oldest first
+  history: &'a [String],
+  // index into history of the recalled query, equal to the length of history when the criteria
+  // are being entered
+  history_idx: usize,
+  // the criteria that were being entered before a query was recalled from history
+  unsaved_criteria: String,
 
   // first visible choice, used like a sliding window the user pushes around
   first_visible_option_idx: usize,
 }
 
-impl<'a, 'b> Selector<'a, 'b> {
+impl<'a, 'b, T: SelectorItem> Selector<'a, 'b, T> {
   pub fn new(
-    terminal: &'a mut Tty,
-    choices: &'b Vec<String>,
-    conf: &'b Config,
-  ) -> Selector<'a, 'b> {
-    let max_height = terminal.max_height as usize;
-    let height = if conf.window.height > 0 {
-      std::cmp::min(conf.window.height as usize, max_height)
+    terminal: &'a mut dyn Terminal,
+    choices: &'b [T],
+    header_lines: &'a [String],
+    history: &'a [String],
+    boosts: &'a [isize],
+    conf: &'a Config,
+  ) -> Selector<'a, 'b, T> {
+    let height = Self::calculate_height(terminal, conf);
+    let header = conf
+      .header
+      .iter()
+      .flat_map(|header| header.lines())
+      .chain(header_lines.iter().map(|header_line| header_line.as_str()))
+      .collect();
+    let ansi_choices = if conf.ansi {
+      choices
+        .iter()
+        .map(|choice| AnsiString::new(choice.display_text()))
+        .collect()
     } else {
-      std::cmp::max(max_height + (conf.window.height as usize), 1)
+      Vec::new()
     };
 
     Selector {
-      terminal,
+      terminal: Renderer::new(terminal, conf.window.synchronized_output),
       choices,
       matches: Vec::new(),
       conf,
+      boosts,
+      ansi_choices,
       height,
       selected: 0,
       criteria: String::new(),
+      marked: BTreeSet::new(),
+      query_accepted: false,
+      key: None,
+      done: false,
+      needs_match: false,
+      needs_redraw: false,
+      header,
+      frame: Frame::new(&conf.window),
+      history,
+      history_idx: history.len(),
+      unsaved_criteria: String::new(),
       first_visible_option_idx: 0,
     }
   }
 
-  pub fn get_match(&mut self) -> io::Result<&'b String> {
-    self.draw_options()?;
-    self.terminal.flush();
+  // min of terminal height or config height, where a non-positive config height is relative to
+  // the terminal height
+  fn calculate_height(terminal: &dyn Terminal, conf: &Config) -> usize {
+    let max_height = terminal.height() as i32;
+    if conf.window.height > 0 {
+      std::cmp::min(conf.window.height, max_height) as usize
+    } else {
+      std::cmp::max(max_height + conf.window.height, 1) as usize
+    }
+  }
+
+  pub fn get_match(&mut self) -> io::Result<Selection<'b, T>> {
+    // the cursor is kept on the prompt row, leave space above it for the frame
+    for _ in 0..self.frame.top() {
+      self.terminal.newline()?;
+    }
+    self.redraw()?;
 
     let actions = Self::build_actions(&self.conf.bindings)?;
-    let input_reader = self.terminal.get_reader();
+    let frame_interval = match self.conf.window.max_fps {
+      0 => Duration::ZERO,
+      max_fps => Duration::from_secs(1) / max_fps,
+    };
+    let mut last_frame = Instant::now();
 
-    loop {
-      let data = input_reader.read()?;
-      if data[0] == 0 {
-        // signal interrupt, redraw screen in case it was WINCH
-        self.redraw()?;
-        continue;
+    while !self.done {
+      // apply all of the input that is pending before drawing, waiting for more until the next
+      // frame is due
+      let mut event = Some(self.terminal.read_event()?);
+      while let Some(pending) = event {
+        self.handle_event(pending, &actions)?;
+        if self.done {
+          break;
+        }
+        let wait = (last_frame + frame_interval).saturating_duration_since(Instant::now());
+        event = self.terminal.poll_event(wait)?;
       }
-      let str_ptr_result = unsafe { CStr::from_ptr(data.as_ptr() as *mut i8) }.to_str();
-      match str_ptr_result {
-        Ok(input) => {
-          if input == "\r" {
-            break;
-          }
 
-          let mut chars = input.chars();
-          let first_char = chars.next();
-          if first_char.is_none() {
-            continue;
-          }
+      if !self.done {
+        last_frame = Instant::now();
+        self.draw_frame()?;
+      }
+    }
+    self.match_pending()?;
 
-          if first_char.unwrap().is_ascii_control() {
-            let action = actions.get(input);
-            if action.is_some() {
-              action.unwrap()(self)?;
-            }
-          } else {
-            self.criteria.push_str(input);
-            self.terminal.print(input)?;
-            self.terminal.flush();
-            self.update_matches()?;
-          }
-        }
-        Err(_) => {
-          return other_error!("Could not convert string");
+    self.clear()?;
+    self.terminal.flush()?;
+
+    let indexes: Vec<usize> = if self.query_accepted {
+      Vec::new()
+    } else if self.marked.is_empty() {
+      self.selected_choice_idx().into_iter().collect()
+    } else {
+      self.marked.iter().copied().collect()
+    };
+    let choices = self.choices;
+    Ok(Selection {
+      key: self.key.take(),
+      items: indexes.iter().map(|&idx| &choices[idx]).collect(),
+      indexes,
+      query: std::mem::take(&mut self.criteria),
+      query_accepted: self.query_accepted,
+    })
+  }
+
+  // Apply a key press or resize, drawing is left until all of the pending events are applied
+  fn handle_event(
+    &mut self,
+    event: Event,
+    actions: &HashMap<String, Action<Self>>,
+  ) -> io::Result<()> {
+    let input = match event {
+      Event::Key(input) => input,
+      Event::Resize => return self.resize(),
+    };
+    if input == "\r" {
+      self.done = true;
+      return Ok(());
+    }
+    if let Some((_, name)) = self.conf.expect.iter().find(|(key, _)| *key == input) {
+      self.key = Some(name.clone());
+      self.done = true;
+      return Ok(());
+    }
+
+    match input.chars().next() {
+      None => {}
+      Some(first_char) if first_char.is_ascii_control() => {
+        if let Some(action) = actions.get(&input) {
+          action(self)?;
         }
       }
+      Some(_) => {
+        self.criteria.push_str(&input);
+        self.needs_match = true;
+      }
     }
+    Ok(())
+  }
 
-    if self.criteria.len() == 0 {
-      Ok(&self.choices[self.selected])
+  // match the criteria when they have changed and draw the window when anything has changed
+  fn draw_frame(&mut self) -> io::Result<()> {
+    self.match_pending()?;
+    if self.needs_redraw {
+      self.redraw()?;
+    }
+    Ok(())
+  }
+
+  // index into choices of the selected option, this is None when nothing matches the criteria
+  fn selected_choice_idx(&self) -> Option<usize> {
+    if self.criteria.is_empty() {
+      Some(self.selected).filter(|&idx| idx < self.choices.len())
     } else {
-      Ok(self.matches[self.selected].choice)
+      self
+        .matches
+        .get(self.selected)
+        .map(|thismatch| thismatch.idx)
     }
   }
 
+  // remove everything that was drawn leaving the cursor where the window started
+  fn clear(&mut self) -> io::Result<()> {
+    self.terminal.set_normal()?;
+    self.terminal.move_up(self.frame.top())?;
+    self.terminal.set_col(0)?;
+    self.terminal.clear_screen_below()
+  }
+
+  fn resize(&mut self) -> io::Result<()> {
+    self.terminal.update_size()?;
+    self.height = Self::calculate_height(&self.terminal, self.conf);
+
+    // the terminal may have reflowed the previous output so remove anything left over from it
+    self.clear()?;
+    self.terminal.move_down(self.frame.top())?;
+    self.needs_redraw = true;
+    Ok(())
+  }
+
   fn redraw(&mut self) -> io::Result<()> {
+    self.needs_redraw = false;
     self.draw_options()?;
     self.terminal.print(&self.criteria)?;
-    self.terminal.flush();
-    Ok(())
+    if self.conf.window.info == InfoStyle::Inline {
+      self.draw_inline_info(None, self.option_count())?;
+    } else {
+      self.end_row()?;
+      self.terminal.set_col(self.cursor_col())?;
+    }
+    self.terminal.flush()
   }
 
-  // draw choices if there are no criteria, otherwise draw matches
-  fn draw_options(&mut self) -> io::Result<()> {
-    let has_criteria = self.criteria.len() != 0;
-    let option_count = if has_criteria {
-      self.matches.len()
+  // column of the cursor at the end of the criteria
+  fn cursor_col(&self) -> usize {
+    self.frame.left()
+      + line::display_width(&self.conf.window.prompt)
+      + line::display_width(&self.criteria)
+  }
+
+  // width available for the contents of each row
+  fn content_width(&self) -> usize {
+    self
+      .terminal
+      .width()
+      .saturating_sub(self.frame.left() + self.frame.right())
+  }
+
+  // move to the next row and draw the left side of the frame
+  fn begin_row(&mut self) -> io::Result<()> {
+    self.terminal.newline()?;
+    self.frame.draw_row_start(&mut self.terminal)
+  }
+
+  // clear the rest of the row and draw the right side of the frame
+  fn end_row(&mut self) -> io::Result<()> {
+    let width = self.terminal.width();
+    self.frame.draw_row_end(&mut self.terminal, width)
+  }
+
+  // lines available to show options, the prompt, info row and header are not included
+  fn options_height(&self) -> usize {
+    let info_height = if self.conf.window.info == InfoStyle::Row {
+      1
     } else {
-      self.choices.len()
+      0
     };
+    let used_height = self.frame.top() + 1 + info_height + self.header.len() + self.frame.bottom();
+    std::cmp::max(self.height.saturating_sub(used_height), 1)
+  }
+
+  // Print the number of matching options out of the total number of choices along with the
+  // number of marked choices when there are any. The spinner is shown when the match count is
+  // for a search that is still in progress.
+  fn draw_info(&mut self, spinner: Option<char>, match_count: usize) -> io::Result<()> {
+    let mut info = String::new();
+    if let Some(frame) = spinner {
+      info.push(frame);
+      info.push(' ');
+    }
+    info.push_str(&format!("{}/{}", match_count, self.choices.len()));
+    if !self.marked.is_empty() {
+      info.push_str(&format!(" ({})", self.marked.len()));
+    }
+
+    if let Some(color) = Self::color(self.conf, "info") {
+      self.terminal.set_fg(color)?;
+    }
+    self.terminal.print(&info)?;
+    self.terminal.set_normal()
+  }
+
+  // draw the info after the criteria then move the cursor back to the end of the criteria
+  fn draw_inline_info(&mut self, spinner: Option<char>, match_count: usize) -> io::Result<()> {
+    let cursor_col = self.cursor_col();
+    self.terminal.set_col(cursor_col)?;
+    self.terminal.print("  ")?;
+    self.draw_info(spinner, match_count)?;
+    self.end_row()?;
+    self.terminal.set_col(cursor_col)
+  }
+
+  // update the criteria and the info while matching is in progress without drawing the options
+  fn draw_progress(&mut self, spinner: char, match_count: usize) -> io::Result<()> {
+    let criteria_col = self.frame.left() + line::display_width(&self.conf.window.prompt);
+    self.terminal.set_col(criteria_col)?;
+    self.terminal.print(&self.criteria)?;
+    self.end_row()?;
+    self.terminal.set_col(self.cursor_col())?;
 
-    let visible_option_count = std::cmp::min(self.height - 1, option_count);
+    match self.conf.window.info {
+      InfoStyle::Inline => self.draw_inline_info(Some(spinner), match_count)?,
+      InfoStyle::Row => {
+        self.terminal.move_down(1)?;
+        self.terminal.set_col(0)?;
+        self.frame.draw_row_start(&mut self.terminal)?;
+        self.draw_info(Some(spinner), match_count)?;
+        self.end_row()?;
+        self.terminal.move_up(1)?;
+        self.terminal.set_col(self.cursor_col())?;
+      }
+      InfoStyle::Hidden => {}
+    }
+    self.terminal.flush()
+  }
+
+  // number of choices when there are no criteria, otherwise the number of matches
+  fn option_count(&self) -> usize {
+    if self.criteria.is_empty() {
+      self.choices.len()
+    } else {
+      self.matches.len()
+    }
+  }
+
+  // draw choices if there are no criteria, otherwise draw matches
+  fn draw_options(&mut self) -> io::Result<()> {
+    let has_criteria = !self.criteria.is_empty();
+    let option_count = self.option_count();
+
+    let visible_option_count = std::cmp::min(self.options_height(), option_count);
     if self.selected >= self.first_visible_option_idx + visible_option_count {
       self.first_visible_option_idx = self.selected + 1 - visible_option_count;
     } else if self.selected < self.first_visible_option_idx {
       self.first_visible_option_idx = self.selected;
     }
 
+    let width = self.terminal.width();
+    self.terminal.move_up(self.frame.top())?;
+    self.terminal.set_col(0)?;
+    self.frame.draw_top(&mut self.terminal, width)?;
+
+    // the prompt is drawn last so that the cursor is left after it, start from the row below it
+    let mut row_count = 0;
+    if self.conf.window.info == InfoStyle::Row {
+      self.begin_row()?;
+      self.draw_info(None, option_count)?;
+      self.end_row()?;
+      row_count += 1;
+    }
+
+    self.draw_header()?;
+    row_count += self.header.len();
+
     if has_criteria {
       self.draw_matches(visible_option_count)?;
     } else {
       self.draw_choices(visible_option_count)?;
     }
 
+    // keep the same number of rows when there are fewer matches than choices
+    let options_height = std::cmp::min(self.options_height(), self.choices.len());
+    for _ in visible_option_count..options_height {
+      self.begin_row()?;
+      self.end_row()?;
+    }
+    row_count += std::cmp::max(options_height, visible_option_count);
+
+    self.frame.draw_bottom(&mut self.terminal, width)?;
+    row_count += self.frame.bottom();
+
+    self.terminal.clearline()?;
+    self.terminal.move_up(row_count)?;
+
     // move to the "top"
     self.terminal.set_normal()?;
     self.terminal.set_col(0)?;
-    self.terminal.print("> ")?;
+    self.frame.draw_row_start(&mut self.terminal)?;
+    if let Some(color) = Self::color(self.conf, "prompt") {
+      self.terminal.set_fg(color)?;
+    }
+    self.terminal.print(&self.conf.window.prompt)?;
+    self.terminal.set_normal()?;
     self.terminal.clearline()?;
 
     Ok(())
   }
 
+  // length of the sgr sequences at the start of the choice, they are reprinted after highlights
+  fn sgr_prefix_len(choice: &str) -> usize {
+    let last_sgr_byte = tty::find_last_sgr_byte(choice.as_bytes());
+    if last_sgr_byte != 0 {
+      last_sgr_byte + 1
+    } else {
+      0
+    }
+  }
+
+  // the configured colour for part of the interface, colours are ignored in no color mode
+  fn color(conf: &Config, key: &str) -> Option<Color> {
+    if conf.no_color {
+      None
+    } else {
+      conf.colors.get(key).copied()
+    }
+  }
+
+  // highlights are shown as bold and underlined text in no color mode
+  fn set_match_style(terminal: &mut dyn Terminal, conf: &Config) -> io::Result<()> {
+    if conf.no_color {
+      terminal.set_bold()?;
+      terminal.set_underline()
+    } else {
+      terminal.set_fg(Self::color(conf, "match").unwrap_or(Color::Ansi(5)))
+    }
+  }
+
+  // inverts the selected line unless the colours for it have been configured
+  fn set_selected_style(terminal: &mut dyn Terminal, conf: &Config) -> io::Result<()> {
+    let fg = Self::color(conf, "selected-fg");
+    let bg = Self::color(conf, "selected-bg");
+    if fg.is_none() && bg.is_none() {
+      return terminal.set_invert();
+    }
+
+    if let Some(color) = fg {
+      terminal.set_fg(color)?;
+    }
+    if let Some(color) = bg {
+      terminal.set_bg(color)?;
+    }
+    Ok(())
+  }
+
+  // the gutter holds the pointer and the marker followed by a space
+  fn gutter_width(conf: &Config) -> usize {
+    line::display_width(&conf.window.pointer) + line::display_width(&conf.window.marker) + 1
+  }
+
+  // print the text in the given colour when shown, otherwise print spaces of the same width
+  fn draw_gutter_part(
+    terminal: &mut dyn Terminal,
+    conf: &Config,
+    text: &str,
+    color_key: &str,
+    is_shown: bool,
+  ) -> io::Result<()> {
+    if !is_shown {
+      return terminal.print(&" ".repeat(line::display_width(text)));
+    }
+
+    if let Some(color) = Self::color(conf, color_key) {
+      terminal.set_fg(color)?;
+    }
+    terminal.print(text)?;
+    terminal.set_normal()
+  }
+
+  // the gutter shows a pointer beside the selected option and a marker beside marked options
+  fn draw_gutter(
+    terminal: &mut dyn Terminal,
+    conf: &Config,
+    is_selected: bool,
+    is_marked: bool,
+  ) -> io::Result<()> {
+    Self::draw_gutter_part(terminal, conf, &conf.window.pointer, "pointer", is_selected)?;
+    Self::draw_gutter_part(terminal, conf, &conf.window.marker, "marker", is_marked)?;
+    terminal.print(" ")
+  }
+
+  fn draw_header(&mut self) -> io::Result<()> {
+    for header_idx in 0..self.header.len() {
+      self.begin_row()?;
+      self.draw_header_line(self.header[header_idx])?;
+      self.end_row()?;
+    }
+    Ok(())
+  }
+
+  fn draw_header_line(&mut self, header_line: &str) -> io::Result<()> {
+    let width = self
+      .content_width()
+      .saturating_sub(Self::gutter_width(self.conf));
+    Self::draw_gutter(&mut self.terminal, self.conf, false, false)?;
+
+    // in ansi mode the colours of the line take over from the header colour where they are set
+    if let Some(color) = Self::color(self.conf, "header") {
+      self.terminal.set_fg(color)?;
+    }
+
+    if self.conf.ansi {
+      let ansi_line = AnsiString::new(header_line);
+      return Self::draw_ansi_line(
+        &mut self.terminal,
+        self.conf,
+        header_line,
+        &ansi_line,
+        &[],
+        false,
+        width,
+      );
+    }
+
+    let mut writer = LineWriter::new(
+      &mut self.terminal,
+      line::display_width(header_line),
+      0,
+      width,
+    )?;
+    writer.print(&mut self.terminal, header_line)?;
+    writer.finish(&mut self.terminal)?;
+    self.terminal.set_normal()
+  }
+
   fn draw_choices(&mut self, visible_option_count: usize) -> io::Result<()> {
     for line_idx in 0..visible_option_count {
-      self.terminal.newline()?;
+      self.begin_row()?;
       let choice_idx = line_idx + self.first_visible_option_idx;
-      let choice = &self.choices[choice_idx];
-
-      if choice_idx == self.selected {
-        // this ensures that the invert sgr is not cleared by a reset byte
-        let last_sgr_byte = tty::find_last_sgr_byte(choice.as_bytes());
-        if last_sgr_byte != 0 {
-          self.terminal.print(&choice[0..last_sgr_byte])?;
-          self.terminal.print(";7")?;
-          self.terminal.print(&choice[last_sgr_byte..])?;
-        } else {
-          self.terminal.set_invert()?;
-          self.terminal.print(choice)?;
-        }
-        self.terminal.set_normal()?;
-      } else {
-        self.terminal.print(choice)?;
-      }
+      self.draw_choice_line(choice_idx, choice_idx == self.selected)?;
+      self.end_row()?;
     }
+    Ok(())
+  }
 
-    self.terminal.clearline()?;
-    self.terminal.move_up(visible_option_count as i32)?;
+  fn draw_choice_line(&mut self, choice_idx: usize, is_selected: bool) -> io::Result<()> {
+    let width = self
+      .content_width()
+      .saturating_sub(Self::gutter_width(self.conf));
+    let choice = self.choices[choice_idx].display_text();
+    let is_marked = self.marked.contains(&choice_idx);
+    Self::draw_gutter(&mut self.terminal, self.conf, is_selected, is_marked)?;
+
+    if self.conf.ansi {
+      let ansi_choice = &self.ansi_choices[choice_idx];
+      Self::draw_ansi_line(
+        &mut self.terminal,
+        self.conf,
+        choice,
+        ansi_choice,
+        &[],
+        is_selected,
+        width,
+      )?;
+      return Ok(());
+    }
+
+    // the selected style follows the leading sgr so that it is not cleared by a reset byte
+    let text_start = Self::sgr_prefix_len(choice);
+    self.terminal.print(&choice[0..text_start])?;
+    if is_selected {
+      Self::set_selected_style(&mut self.terminal, self.conf)?;
+    }
+    let text = &choice[text_start..];
+
+    let mut writer = LineWriter::new(&mut self.terminal, line::display_width(text), 0, width)?;
+    writer.print(&mut self.terminal, text)?;
+    writer.finish(&mut self.terminal)?;
+
+    if is_selected {
+      self.terminal.set_normal()?;
+    }
 
     Ok(())
   }
 
   fn draw_matches(&mut self, visible_option_count: usize) -> io::Result<()> {
     for line_idx in 0..visible_option_count {
-      self.terminal.newline()?;
-      let match_idx = line_idx + self.first_visible_option_idx;
-      let thismatch = &self.matches[match_idx];
-      let choice = thismatch.choice;
+      self.begin_row()?;
+      self.draw_match_line(line_idx + self.first_visible_option_idx)?;
+      self.end_row()?;
+    }
+    Ok(())
+  }
+
+  fn draw_match_line(&mut self, match_idx: usize) -> io::Result<()> {
+    let thismatch = &self.matches[match_idx];
+    let is_selected = match_idx == self.selected;
+    if !self.choices[thismatch.idx].highlights_display_text() {
+      // the match ranges do not refer to the displayed text so cannot be highlighted
+      return self.draw_choice_line(thismatch.idx, is_selected);
+    }
+
+    let width = self
+      .content_width()
+      .saturating_sub(Self::gutter_width(self.conf));
+    let choice = self.choices[thismatch.idx].display_text();
+    let is_marked = self.marked.contains(&thismatch.idx);
+    Self::draw_gutter(&mut self.terminal, self.conf, is_selected, is_marked)?;
+
+    if self.conf.ansi {
+      let ansi_choice = &self.ansi_choices[thismatch.idx];
+      let ranges = &thismatch.ranges;
+      Self::draw_ansi_line(
+        &mut self.terminal,
+        self.conf,
+        choice,
+        ansi_choice,
+        ranges,
+        is_selected,
+        width,
+      )?;
+      return Ok(());
+    }
+
+    let text_start = Self::sgr_prefix_len(choice);
+    self.terminal.print(&choice[0..text_start])?;
+    if is_selected {
+      Self::set_selected_style(&mut self.terminal, self.conf)?;
+    }
+
+    // scroll horizontally so that the matched characters are visible
+    let offset = match (thismatch.ranges.first(), thismatch.ranges.last()) {
+      (Some(first), Some(last)) if first.0 >= text_start => line::scroll_offset(
+        line::display_width(&choice[text_start..first.0]),
+        line::display_width(&choice[text_start..last.0 + last.1]),
+        width,
+      ),
+      _ => 0,
+    };
+    let mut writer = LineWriter::new(
+      &mut self.terminal,
+      line::display_width(&choice[text_start..]),
+      offset,
+      width,
+    )?;
+
+    let mut last_range_end = text_start;
+    for range in &thismatch.ranges {
+      if range.0 < text_start {
+        // ignore matches within the leading sgr sequences
+        continue;
+      }
+      if last_range_end < range.0 {
+        // print text before the match
+        writer.print(&mut self.terminal, &choice[last_range_end..range.0])?;
+      }
+      Self::set_match_style(&mut self.terminal, self.conf)?;
+      let range_end = range.0 + range.1;
+      writer.print(&mut self.terminal, &choice[range.0..range_end])?;
+      self.terminal.set_normal()?;
+      self.terminal.print(&choice[0..text_start])?;
+      if is_selected {
+        Self::set_selected_style(&mut self.terminal, self.conf)?;
+      }
+      last_range_end = range_end;
+    }
+    if last_range_end < choice.len() {
+      writer.print(&mut self.terminal, &choice[last_range_end..choice.len()])?;
+    }
+    writer.finish(&mut self.terminal)?;
 
-      let is_selected = match_idx == self.selected;
+    if is_selected {
+      self.terminal.set_normal()?;
+    }
+
+    Ok(())
+  }
+
+  // Draw a line that may contain escape sequences anywhere within it, the ranges refer to the
+  // text with the escape sequences removed
+  fn draw_ansi_line(
+    terminal: &mut dyn Terminal,
+    conf: &Config,
+    choice: &str,
+    ansi_choice: &AnsiString,
+    ranges: &[(usize, usize)],
+    is_selected: bool,
+    width: usize,
+  ) -> io::Result<()> {
+    let text = &ansi_choice.text;
 
-      let last_sgr_byte = tty::find_last_sgr_byte(choice.as_bytes());
-      if last_sgr_byte != 0 {
-        self.terminal.print(&choice[0..last_sgr_byte])?;
+    // scroll horizontally so that the matched characters are visible
+    let offset = match (ranges.first(), ranges.last()) {
+      (Some(first), Some(last)) => line::scroll_offset(
+        line::display_width(&text[0..first.0]),
+        line::display_width(&text[0..last.0 + last.1]),
+        width,
+      ),
+      _ => 0,
+    };
+    let mut writer = LineWriter::new(terminal, line::display_width(text), offset, width)?;
+
+    if is_selected {
+      Self::set_selected_style(terminal, conf)?;
+    }
+
+    let mut sgr_state = SgrState::new();
+    let mut escapes = ansi_choice.escapes.iter().peekable();
+    let mut ranges = ranges.iter().peekable();
+    let mut highlight_end: Option<usize> = None;
+    let mut pos = 0;
+    loop {
+      if highlight_end == Some(pos) {
+        // restore the colours from the choice that the highlight replaced
+        terminal.set_normal()?;
+        terminal.print(sgr_state.as_str())?;
         if is_selected {
-          self.terminal.print(";7")?;
+          Self::set_selected_style(terminal, conf)?;
         }
-      } else if is_selected {
-        self.terminal.set_invert()?;
+        highlight_end = None;
       }
 
-      let mut last_range_end = last_sgr_byte;
-      for range in &thismatch.ranges {
-        if last_range_end < range.0 {
-          // print text before the match
-          self.terminal.print(&choice[last_range_end..range.0])?;
-        }
-        self.terminal.set_fg(5)?;
-        let range_end = range.0 + range.1;
-        self.terminal.print(&choice[range.0..range_end])?;
-        self.terminal.set_normal()?;
-        if last_sgr_byte != 0 {
-          self.terminal.print(&choice[0..last_sgr_byte])?;
+      while let Some((_, start, end)) = escapes.next_if(|escape| escape.0 == pos) {
+        let sequence = &choice[*start..*end];
+        // other escape sequences could move the cursor so they are dropped, within a highlight
+        // the sgr state is tracked but not drawn so that the highlight colour remains
+        if sgr_state.apply(sequence) && highlight_end.is_none() {
+          terminal.print(sequence)?;
           if is_selected {
-            self.terminal.print(";7m")?;
-          } else {
-            self.terminal.print("m")?;
+            Self::set_selected_style(terminal, conf)?;
           }
-        } else if is_selected {
-          self.terminal.set_invert()?;
         }
-        last_range_end = range_end;
       }
-      if last_range_end < choice.len() {
-        self.terminal.print(&choice[last_range_end..choice.len()])?;
+
+      if pos == text.len() {
+        break;
       }
 
-      if is_selected {
-        self.terminal.set_normal()?;
+      if highlight_end.is_none() {
+        if let Some((start, len)) = ranges.next_if(|range| range.0 == pos) {
+          Self::set_match_style(terminal, conf)?;
+          highlight_end = Some(start + len);
+        }
       }
+
+      let mut next = text.len();
+      if let Some(escape) = escapes.peek() {
+        next = std::cmp::min(next, escape.0);
+      }
+      match highlight_end {
+        Some(end) => next = std::cmp::min(next, end),
+        None => {
+          if let Some(range) = ranges.peek() {
+            next = std::cmp::min(next, range.0);
+          }
+        }
+      }
+
+      writer.print(terminal, &text[pos..next])?;
+      pos = next;
     }
 
-    let screen_height = std::cmp::min(self.height - 1, self.choices.len());
-    for _ in visible_option_count..screen_height {
-      self.terminal.newline()?;
+    writer.finish(terminal)?;
+    terminal.set_normal()
+  }
+
+  // the text of a choice that the criteria are matched against
+  fn match_text(&self, idx: usize) -> &str {
+    let choice = &self.choices[idx];
+    if !choice.highlights_display_text() {
+      choice.match_text()
+    } else if self.conf.ansi {
+      &self.ansi_choices[idx].text
+    } else {
+      choice.display_text()
     }
+  }
 
-    self.terminal.clearline()?;
-    self.terminal.move_up(screen_height as i32)?;
+  // order matches with equal scores using the configured tiebreak criteria
+  fn tiebreak(&self, a: &Match, b: &Match) -> Ordering {
+    let begin = |m: &Match| m.ranges.first().map_or(0, |&(start, _)| start);
+    let end_distance = |m: &Match| {
+      let end = m.ranges.last().map_or(0, |&(start, len)| start + len);
+      self.match_text(m.idx).len() - end
+    };
 
-    Ok(())
+    self
+      .conf
+      .tiebreak
+      .iter()
+      .map(|tiebreak| match tiebreak {
+        Tiebreak::Length => line::display_width(self.match_text(a.idx))
+          .cmp(&line::display_width(self.match_text(b.idx))),
+        Tiebreak::Begin => begin(a).cmp(&begin(b)),
+        Tiebreak::End => end_distance(a).cmp(&end_distance(b)),
+        Tiebreak::Index => a.idx.cmp(&b.idx),
+      })
+      .find(|&ordering| ordering != Ordering::Equal)
+      .unwrap_or(Ordering::Equal)
   }
 
+  // match the criteria against the choices, the window needs redrawing afterwards
   fn update_matches(&mut self) -> io::Result<()> {
-    let mut matches: Vec<Match<'b>> = self
-      .choices
-      // TODO: use par_iter from rayon
-      .iter()
-      .map(|choice| match best_match(&self.criteria, choice) {
-        None => None,
-        Some(v) => Some(Match {
-          ranges: v.continuous_matches(),
-          score: v.score(),
-          choice: &choice,
+    let started = Instant::now();
+    let mut matches: Vec<Match> = Vec::new();
+
+    // match in chunks so that progress can be shown when there are many choices
+    for (chunk_idx, chunk) in self.choices.chunks(MATCH_CHUNK_SIZE).enumerate() {
+      if started.elapsed() >= SPINNER_DELAY {
+        let spinner = SPINNER_FRAMES[chunk_idx % SPINNER_FRAMES.len()];
+        self.draw_progress(spinner, matches.len())?;
+      }
+
+      let chunk_start = chunk_idx * MATCH_CHUNK_SIZE;
+      matches.extend(
+        // TODO: use par_iter from rayon
+        (chunk_start..chunk_start + chunk.len()).filter_map(|idx| {
+          let text = self.match_text(idx);
+          best_match(&self.criteria, text).map(|v| Match {
+            idx,
+            ranges: char_ranges_to_byte_ranges(text, v.continuous_matches()),
+            score: v.score() + self.boosts.get(idx).copied().unwrap_or(0),
+          })
         }),
-      })
-      .flatten()
-      .collect();
+      );
+    }
 
-    matches.sort_by(|a, b| b.score.cmp(&a.score));
+    if !self.conf.no_sort {
+      // sort_by is stable so matches that are still tied keep their input order
+      matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| self.tiebreak(a, b)));
+    }
     self.selected = 0;
     self.matches = matches;
-    self.redraw()
+    self.needs_match = false;
+    self.needs_redraw = true;
+    Ok(())
+  }
+
+  // match criteria that were typed earlier in the batch of keys before anything reads the matches,
+  // the selection or the marks
+  fn match_pending(&mut self) -> io::Result<()> {
+    if self.needs_match {
+      self.update_matches()?;
+    }
+    Ok(())
   }
 
   fn build_actions(
     bindings: &HashMap<String, String>,
-  ) -> io::Result<HashMap<String, fn(&mut Self) -> io::Result<()>>> {
-    let mut actions_by_name: HashMap<String, fn(&mut Self) -> io::Result<()>> = HashMap::new();
+  ) -> io::Result<HashMap<String, Action<Self>>> {
+    let mut actions_by_name: HashMap<String, Action<Self>> = HashMap::new();
     def_action_names!(
       actions_by_name,
       "select-prev" => select_prev;
       "select-next" => select_next;
       "backspace" => backspace;
+      "toggle-mark" => toggle_mark;
+      "accept-query" => accept_query;
+      "previous-history" => previous_history;
+      "next-history" => next_history;
     );
 
-    let mut actions: HashMap<_, fn(&mut Self) -> io::Result<()>> = HashMap::new();
+    let mut actions: HashMap<_, Action<Self>> = HashMap::new();
     for (a, b) in bindings {
-      let action = actions_by_name.get(b);
-      if !action.is_some() {
-        return other_error!(format!("Invalid action name '{}'", b));
-      }
-
-      actions.insert(a.clone(), *action.unwrap());
+      match actions_by_name.get(b) {
+        Some(action) => actions.insert(a.clone(), *action),
+        None => return other_error!(format!("Invalid action name '{}'", b)),
+      };
     }
 
     def_default_mappings!(
@@ -303,32 +971,503 @@ impl<'a, 'b> Selector<'a, 'b> {
 
       control_key!(b'h') => backspace;
       "\x7f" => backspace;
+
+      control_key!(b'i') => toggle_mark;
     );
     Ok(actions)
   }
 
   fn select_next(selector: &mut Self) -> io::Result<()> {
-    if selector.selected + 1 < selector.choices.len() {
+    selector.match_pending()?;
+    if selector.selected + 1 < selector.option_count() {
       selector.selected += 1;
-      selector.redraw()?;
+      selector.needs_redraw = true;
     }
     Ok(())
   }
 
   fn select_prev(selector: &mut Self) -> io::Result<()> {
+    selector.match_pending()?;
     if selector.selected > 0 {
       selector.selected -= 1;
-      selector.redraw()?;
+      selector.needs_redraw = true;
+    }
+    Ok(())
+  }
+
+  // mark or unmark the selected option then select the next one
+  fn toggle_mark(selector: &mut Self) -> io::Result<()> {
+    if !selector.conf.multi {
+      return Ok(());
+    }
+    selector.match_pending()?;
+    if let Some(choice_idx) = selector.selected_choice_idx() {
+      if !selector.marked.remove(&choice_idx) {
+        selector.marked.insert(choice_idx);
+      }
+      if selector.selected + 1 < selector.option_count() {
+        selector.selected += 1;
+      }
+      selector.needs_redraw = true;
+    }
+    Ok(())
+  }
+
+  // finish with the criteria as they were entered even when nothing matches them
+  fn accept_query(selector: &mut Self) -> io::Result<()> {
+    selector.query_accepted = true;
+    selector.done = true;
+    Ok(())
+  }
+
+  // replace the criteria with a query from history
+  fn recall_history(&mut self, history_idx: usize) -> io::Result<()> {
+    if self.history_idx == self.history.len() {
+      self.unsaved_criteria = std::mem::take(&mut self.criteria);
+    }
+    self.history_idx = history_idx;
+    self.criteria = if history_idx == self.history.len() {
+      std::mem::take(&mut self.unsaved_criteria)
+    } else {
+      self.history[history_idx].clone()
+    };
+    self.first_visible_option_idx = 0;
+    self.selected = 0;
+    if self.criteria.is_empty() {
+      self.needs_redraw = true;
+    } else {
+      self.needs_match = true;
+    }
+    Ok(())
+  }
+
+  fn previous_history(selector: &mut Self) -> io::Result<()> {
+    if selector.history_idx > 0 {
+      selector.recall_history(selector.history_idx - 1)?;
+    }
+    Ok(())
+  }
+
+  fn next_history(selector: &mut Self) -> io::Result<()> {
+    if selector.history_idx < selector.history.len() {
+      selector.recall_history(selector.history_idx + 1)?;
     }
     Ok(())
   }
 
   fn backspace(selector: &mut Self) -> io::Result<()> {
-    if selector.criteria.len() != 0 {
+    if !selector.criteria.is_empty() {
       selector.criteria.pop();
-      selector.update_matches()?;
-      selector.redraw()?;
+      selector.needs_match = true;
     }
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{config::WindowConfig, render::Style, screen::Screen};
+
+  const UP: &str = tty::KEY_UP;
+  const DOWN: &str = tty::KEY_DOWN;
+
+  // Run the selector with the keys then return the screen as it was left when the keys ran out
+  fn draw(screen: &mut Screen, choices: &[&str], conf: &Config, keys: &[&str]) {
+    screen.push_keys(keys);
+    let result = Selector::new(screen, choices, &[], &[], &[], conf).get_match();
+    assert!(
+      result.is_err(),
+      "the selector finished before the keys ran out"
+    );
+  }
+
+  fn select(choices: &[&str], conf: &Config, keys: &[&str]) -> (Vec<usize>, String) {
+    let mut screen = Screen::new(40, 10);
+    screen.push_keys(keys);
+    let selection = Selector::new(&mut screen, choices, &[], &[], &[], conf)
+      .get_match()
+      .unwrap();
+    (selection.indexes, selection.query)
+  }
+
+  fn is_match(style: &Style) -> bool {
+    style.fg == Color::Ansi(5)
+  }
+
+  fn is_inverted(style: &Style) -> bool {
+    style.invert
+  }
+
+  #[test]
+  fn draws_choices_below_the_prompt() {
+    let mut screen = Screen::new(20, 5);
+    draw(
+      &mut screen,
+      &["alpha", "beta", "gamma"],
+      &Config::default(),
+      &[],
+    );
+
+    assert_eq!(
+      screen.lines(),
+      [">   3/3", ">  alpha", "   beta", "   gamma"]
+    );
+    assert_eq!(screen.marks(1, is_inverted), "   ^^^^^");
+    assert_eq!(screen.marks(2, is_inverted), "");
+    assert_eq!(screen.cursor(), (0, 2));
+  }
+
+  #[test]
+  fn highlights_matched_characters() {
+    let mut screen = Screen::new(20, 5);
+    draw(
+      &mut screen,
+      &["xyz", "cab", "abc"],
+      &Config::default(),
+      &["a", "b"],
+    );
+
+    assert_eq!(screen.lines(), ["> ab  2/3", ">  abc", "   cab"]);
+    assert_eq!(screen.marks(1, is_match), "   ^^");
+    assert_eq!(screen.marks(1, is_inverted), "   ^^^");
+    assert_eq!(screen.marks(2, is_match), "    ^^");
+    assert_eq!(screen.cursor(), (0, 4));
+  }
+
+  #[test]
+  fn highlights_items_only_when_they_match_on_the_display_text() {
+    struct Item {
+      display: &'static str,
+      search: String,
+    }
+    impl SelectorItem for Item {
+      fn display_text(&self) -> &str {
+        self.display
+      }
+      fn match_text(&self) -> &str {
+        &self.search
+      }
+    }
+
+    // the first matches on a copy of its display text, the second on other text
+    let choices = [
+      Item {
+        display: "abc",
+        search: "abc".to_string(),
+      },
+      Item {
+        display: "xyz",
+        search: "xyz ab".to_string(),
+      },
+    ];
+    let mut screen = Screen::new(20, 5);
+    screen.push_keys(&["a", "b"]);
+    let conf = Config::default();
+    let result = Selector::new(&mut screen, &choices, &[], &[], &[], &conf).get_match();
+    assert!(result.is_err());
+
+    assert_eq!(screen.lines(), ["> ab  2/2", ">  abc", "   xyz"]);
+    assert_eq!(screen.marks(1, is_match), "   ^^");
+    assert_eq!(screen.marks(2, is_match), "");
+  }
+
+  #[test]
+  fn highlights_with_attributes_without_colour() {
+    let mut screen = Screen::new(20, 5);
+    let conf = Config {
+      no_color: true,
+      ..Config::default()
+    };
+    draw(&mut screen, &["abc"], &conf, &["b"]);
+
+    assert_eq!(
+      screen.marks(1, |style| style.bold && style.underline),
+      "    ^"
+    );
+    assert_eq!(screen.marks(1, |style| style.fg != Color::Default), "");
+  }
+
+  #[test]
+  fn uses_configured_colours() {
+    let mut screen = Screen::new(20, 5);
+    let mut conf = Config::default();
+    conf
+      .colors
+      .insert("match".to_string(), Color::Rgb(255, 128, 0));
+    conf
+      .colors
+      .insert("selected-bg".to_string(), Color::Indexed(236));
+    draw(&mut screen, &["abc"], &conf, &["b"]);
+
+    assert_eq!(screen.style(1, 4).fg, Color::Rgb(255, 128, 0));
+    assert_eq!(screen.style(1, 3).bg, Color::Indexed(236));
+    assert_eq!(screen.marks(1, is_inverted), "");
+  }
+
+  #[test]
+  fn scrolls_to_keep_the_selected_choice_visible() {
+    let choices: Vec<String> = (0..10).map(|idx| format!("choice {}", idx)).collect();
+    let choices: Vec<&str> = choices.iter().map(String::as_str).collect();
+    let mut screen = Screen::new(20, 4);
+    draw(&mut screen, &choices, &Config::default(), &[DOWN; 5]);
+
+    assert_eq!(
+      screen.lines(),
+      [">   10/10", "   choice 3", "   choice 4", ">  choice 5"]
+    );
+    assert_eq!(screen.marks(3, is_inverted), "   ^^^^^^^^");
+
+    let mut screen = Screen::new(20, 4);
+    screen.push_keys(&[DOWN; 5]);
+    draw(&mut screen, &choices, &Config::default(), &[UP; 3]);
+    assert_eq!(
+      screen.lines(),
+      [">   10/10", ">  choice 2", "   choice 3", "   choice 4"]
+    );
+  }
+
+  #[test]
+  fn only_redraws_the_rows_that_changed() {
+    let mut screen = Screen::new(20, 5);
+    draw(
+      &mut screen,
+      &["alpha", "beta", "gamma"],
+      &Config::default(),
+      &[DOWN],
+    );
+
+    assert_eq!(
+      screen.lines(),
+      [">   3/3", "   alpha", ">  beta", "   gamma"]
+    );
+    let output = screen.take_output();
+    assert_eq!(output.matches("alpha").count(), 2);
+    assert_eq!(output.matches("beta").count(), 2);
+    assert_eq!(output.matches("gamma").count(), 1);
+    // each frame is a synchronized update
+    assert_eq!(output.matches("\x1b[?2026h").count(), 2);
+    assert!(!screen.is_synchronized());
+  }
+
+  #[test]
+  fn scrolls_the_terminal_to_make_space_for_the_window() {
+    let mut screen = Screen::new(20, 4);
+    screen.print("$ one\n$ two\n").unwrap();
+    let conf = Config {
+      window: WindowConfig {
+        height: 3,
+        ..WindowConfig::default()
+      },
+      ..Config::default()
+    };
+    draw(&mut screen, &["alpha", "beta", "gamma"], &conf, &[]);
+
+    assert_eq!(screen.lines(), ["$ two", ">   3/3", ">  alpha", "   beta"]);
+    assert_eq!(screen.cursor(), (1, 2));
+  }
+
+  #[test]
+  fn applies_pending_keys_before_drawing() {
+    let mut screen = Screen::new(20, 5);
+    let choices = ["alpha", "beta", "gamma"];
+    draw(
+      &mut screen,
+      &choices,
+      &Config::default(),
+      &["x", "\x7f", "m", "m", DOWN],
+    );
+
+    assert_eq!(screen.lines(), ["> mm  1/3", ">  gamma"]);
+    assert_eq!(screen.take_output().matches("\x1b[?2026h").count(), 2);
+  }
+
+  #[test]
+  fn matches_typed_keys_before_moving_in_the_same_batch() {
+    let conf = Config::default();
+    assert_eq!(
+      select(&["ab", "ac"], &conf, &["a", DOWN, "\r"]),
+      (vec![1], "a".to_string())
+    );
+
+    let conf = Config {
+      multi: true,
+      ..Config::default()
+    };
+    assert_eq!(
+      select(&["alpha", "beta", "gamma"], &conf, &["\t", "g", "\t", "\r"]),
+      (vec![0, 2], "g".to_string())
+    );
+  }
+
+  #[test]
+  fn draws_control_characters_as_symbols() {
+    let mut screen = Screen::new(20, 3);
+    draw(
+      &mut screen,
+      &["one\ntwo", "a\rb\x7f"],
+      &Config::default(),
+      &[],
+    );
+    assert_eq!(screen.lines(), [">   2/2", ">  one␊two", "   a␍b␡"]);
+  }
+
+  // the query after typing "x" then recalling history with c-p and c-o
+  fn recall_history(keys: &[&str]) -> String {
+    let mut conf = Config::default();
+    conf
+      .bindings
+      .insert("\x10".to_string(), "previous-history".to_string());
+    conf
+      .bindings
+      .insert("\x0f".to_string(), "next-history".to_string());
+    let history = ["old".to_string(), "new".to_string()];
+    let mut screen = Screen::new(20, 5);
+    screen.push_keys(&["x"]);
+    screen.push_keys(keys);
+    screen.push_keys(&["\r"]);
+    let selection = Selector::new(&mut screen, &["old", "new"], &[], &history, &[], &conf)
+      .get_match()
+      .unwrap();
+    selection.query
+  }
+
+  #[test]
+  fn recalls_history_up_to_either_end() {
+    assert_eq!(recall_history(&["\x10"]), "new");
+    assert_eq!(recall_history(&["\x10", "\x10"]), "old");
+    // the oldest entry stays when going further back
+    assert_eq!(recall_history(&["\x10", "\x10", "\x10"]), "old");
+    assert_eq!(recall_history(&["\x10", "\x10", "\x0f"]), "new");
+    // going past the newest entry restores what was typed and goes no further
+    assert_eq!(recall_history(&["\x10", "\x0f"]), "x");
+    assert_eq!(recall_history(&["\x0f", "\x0f"]), "x");
+  }
+
+  #[test]
+  fn clips_long_choices_to_the_width() {
+    let mut screen = Screen::new(12, 3);
+    draw(
+      &mut screen,
+      &["abcdefghijklmnop"],
+      &Config::default(),
+      &["p"],
+    );
+
+    assert_eq!(screen.line(1), ">  …ijklmnop");
+    assert_eq!(screen.marks(1, is_match), "           ^");
+  }
+
+  #[test]
+  fn passes_through_sgr_sequences_in_ansi_mode() {
+    let mut screen = Screen::new(30, 5);
+    let conf = Config {
+      ansi: true,
+      ..Config::default()
+    };
+    let choices = ["\x1b[32mgreen\x1b[0m plain", "other"];
+    draw(&mut screen, &choices, &conf, &[DOWN]);
+
+    assert_eq!(screen.line(1), "   green plain");
+    assert_eq!(
+      screen.marks(1, |style| style.fg == Color::Ansi(2)),
+      "   ^^^^^"
+    );
+
+    // the colour of the choice is restored after a highlight within it
+    draw(&mut screen, &choices, &conf, &["r", "e"]);
+    assert_eq!(screen.line(1), ">  green plain");
+    assert_eq!(screen.marks(1, is_match), "    ^^");
+    assert_eq!(
+      screen.marks(1, |style| style.fg == Color::Ansi(2)),
+      "   ^  ^^"
+    );
+    assert_eq!(screen.marks(1, is_inverted), "   ^^^^^^^^^^^");
+  }
+
+  #[test]
+  fn colours_the_header_in_ansi_mode() {
+    let mut colors = HashMap::new();
+    colors.insert("header".to_string(), Color::Ansi(4));
+    let mut screen = Screen::new(30, 5);
+    let conf = Config {
+      ansi: true,
+      header: Some("title \x1b[32mgreen".to_string()),
+      colors,
+      ..Config::default()
+    };
+    draw(&mut screen, &["choice"], &conf, &[]);
+
+    assert_eq!(screen.line(1), "   title green");
+    assert_eq!(
+      screen.marks(1, |style| style.fg == Color::Ansi(4)),
+      "   ^^^^^^"
+    );
+    assert_eq!(
+      screen.marks(1, |style| style.fg == Color::Ansi(2)),
+      "         ^^^^^"
+    );
+  }
+
+  #[test]
+  fn redraws_after_a_resize() {
+    let mut screen = Screen::new(20, 6);
+    let mut conf = Config::default();
+    conf.window.height = -1;
+    screen.push_keys(&[]);
+    screen.resize(20, 4);
+    draw(
+      &mut screen,
+      &["alpha", "beta", "gamma", "delta", "epsilon"],
+      &conf,
+      &[],
+    );
+
+    // the window shrinks from 5 rows to 3 and the row of the first frame left below it is cleared
+    assert_eq!(screen.lines(), [">   5/5", ">  alpha", "   beta"]);
+    assert_eq!(screen.line(3), "");
+  }
+
+  #[test]
+  fn returns_the_selected_choice() {
+    let choices = ["alpha", "beta", "gamma"];
+    let conf = Config::default();
+    assert_eq!(
+      select(&choices, &conf, &[DOWN, "\r"]),
+      (vec![1], String::new())
+    );
+    assert_eq!(
+      select(&choices, &conf, &["m", "\r"]),
+      (vec![2], "m".to_string())
+    );
+    assert_eq!(
+      select(&choices, &conf, &["z", "\r"]),
+      (vec![], "z".to_string())
+    );
+  }
+
+  #[test]
+  fn breaks_ties_by_the_width_of_the_choices() {
+    // "x é" is shorter on screen but has as many bytes as "x ab"
+    let choices = ["x ab", "x é"];
+    let conf = Config {
+      tiebreak: vec![Tiebreak::Length],
+      ..Config::default()
+    };
+    assert_eq!(
+      select(&choices, &conf, &["x", "\r"]),
+      (vec![1], "x".to_string())
+    );
+  }
+
+  #[test]
+  fn returns_the_marked_choices_in_input_order() {
+    let choices = ["alpha", "beta", "gamma"];
+    let conf = Config {
+      multi: true,
+      ..Config::default()
+    };
+    let (indexes, _) = select(&choices, &conf, &[DOWN, DOWN, "\t", UP, UP, "\t", "\r"]);
+    assert_eq!(indexes, [0, 2]);
+  }
+}
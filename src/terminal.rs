@@ -0,0 +1,256 @@
+use crate::{
+  ansi,
+  color::Color,
+  line, other_error,
+  render::{CellGrid, Style},
+};
+use std::{collections::VecDeque, io, time::Duration};
+
+/// Something read from the terminal
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+  /// The bytes of a single key press or of text that was pasted
+  Key(String),
+  /// The terminal was resized, its size should be queried again with `update_size`
+  Resize,
+}
+
+/// The operations the selector uses to draw itself and read input. Columns and rows are counted
+/// from 0 and text passed to `print` may contain escape sequences.
+pub trait Terminal {
+  /// Number of columns
+  fn width(&self) -> usize;
+  /// Number of rows
+  fn height(&self) -> usize;
+  /// Query the size again after a resize event
+  fn update_size(&mut self) -> io::Result<()>;
+
+  /// Wait for the next key press or resize
+  fn read_event(&mut self) -> io::Result<Event>;
+  /// Wait up to the timeout for the next event, with a zero timeout only an event that has
+  /// already arrived is returned. The selector uses this to apply all of the pending input before
+  /// drawing, terminals that don't implement it are drawn after every event.
+  fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+    let _ = timeout;
+    Ok(None)
+  }
+
+  /// Draw text at the cursor and move the cursor past it
+  fn print(&mut self, text: &str) -> io::Result<()>;
+  /// Clear from the cursor to the end of the line
+  fn clearline(&mut self) -> io::Result<()>;
+  /// Clear from the cursor to the end of the line and all of the lines below it
+  fn clear_screen_below(&mut self) -> io::Result<()>;
+  /// Clear from the cursor to the end of the line then move to the start of the next line,
+  /// scrolling when the cursor is on the last line
+  fn newline(&mut self) -> io::Result<()>;
+  /// Move the cursor to a column of the current row
+  fn set_col(&mut self, col: usize) -> io::Result<()>;
+  /// Move the cursor up without scrolling, a count of 0 doesn't move it
+  fn move_up(&mut self, row_count: usize) -> io::Result<()>;
+  /// Move the cursor down without scrolling, a count of 0 doesn't move it
+  fn move_down(&mut self, row_count: usize) -> io::Result<()>;
+  /// Stop text that reaches the end of the line from wrapping onto the next line
+  fn set_no_wrap(&mut self) -> io::Result<()>;
+  /// Let text that reaches the end of the line wrap onto the next line again
+  fn set_wrap(&mut self) -> io::Result<()>;
+
+  /// Set the colour of the text drawn after this
+  fn set_fg(&mut self, color: Color) -> io::Result<()>;
+  /// Set the background colour of the text drawn after this
+  fn set_bg(&mut self, color: Color) -> io::Result<()>;
+  /// Draw the text after this in bold
+  fn set_bold(&mut self) -> io::Result<()>;
+  /// Underline the text drawn after this
+  fn set_underline(&mut self) -> io::Result<()>;
+  /// Swap the foreground and background colours of the text drawn after this
+  fn set_invert(&mut self) -> io::Result<()>;
+  /// Reset the colours and attributes
+  fn set_normal(&mut self) -> io::Result<()>;
+
+  /// Mark the start of a frame, terminals that support synchronized output show everything drawn
+  /// until the end of the frame at once
+  fn begin_synchronized_update(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+  /// Mark the end of a frame
+  fn end_synchronized_update(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+
+  /// Send everything that was drawn to the terminal
+  fn flush(&mut self) -> io::Result<()>;
+}
+
+/// A terminal that draws to a grid of characters in memory and reads input from a queue of
+/// events, e.g. for tests or to embed the selector in another interface. Colours and attributes
+/// are not recorded.
+pub struct VirtualTerminal {
+  grid: CellGrid,
+  events: VecDeque<Event>,
+}
+
+impl VirtualTerminal {
+  /// A blank screen of the size with the cursor at the top left
+  pub fn new(width: usize, height: usize) -> VirtualTerminal {
+    VirtualTerminal {
+      grid: CellGrid::new(width, height),
+      events: VecDeque::new(),
+    }
+  }
+
+  /// Queue a key press, e.g. `"a"`, `"\r"` or `"\x1b[A"`
+  pub fn push_key(&mut self, key: &str) {
+    self.events.push_back(Event::Key(key.to_string()));
+  }
+
+  /// Change the size of the screen and queue a resize event
+  pub fn resize(&mut self, width: usize, height: usize) {
+    self.grid.resize(width, height);
+    self.events.push_back(Event::Resize);
+  }
+
+  /// The position of the cursor as (row, column)
+  pub fn cursor(&self) -> (usize, usize) {
+    (self.grid.row, self.grid.col)
+  }
+
+  /// The text of each row with trailing spaces removed
+  pub fn lines(&self) -> Vec<String> {
+    (0..self.grid.height)
+      .map(|row| self.grid.line(row))
+      .collect()
+  }
+}
+
+impl Terminal for VirtualTerminal {
+  fn width(&self) -> usize {
+    self.grid.width
+  }
+
+  fn height(&self) -> usize {
+    self.grid.height
+  }
+
+  fn update_size(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+
+  fn read_event(&mut self) -> io::Result<Event> {
+    match self.events.pop_front() {
+      Some(event) => Ok(event),
+      None => other_error!("No more input"),
+    }
+  }
+
+  // the queued events have all arrived so there is never a wait for more
+  fn poll_event(&mut self, _timeout: Duration) -> io::Result<Option<Event>> {
+    Ok(self.events.pop_front())
+  }
+
+  fn print(&mut self, text: &str) -> io::Result<()> {
+    let mut rest = text;
+    while !rest.is_empty() {
+      let escape_len = std::cmp::max(
+        line::escape_sequence_len(rest),
+        ansi::osc_sequence_len(rest),
+      );
+      if escape_len != 0 {
+        rest = &rest[escape_len..];
+        continue;
+      }
+
+      let c = rest.chars().next().unwrap();
+      match c {
+        '\r' => self.grid.col = 0,
+        '\n' => self.newline()?,
+        '\t' => {
+          let next_tab = (self.grid.col / 8 + 1) * 8;
+          self.grid.col = std::cmp::min(next_tab, self.grid.width.saturating_sub(1));
+        }
+        c if c.is_control() => {}
+        c => self.grid.put_char(c, Style::default()),
+      }
+      rest = &rest[c.len_utf8()..];
+    }
+    Ok(())
+  }
+
+  fn clearline(&mut self) -> io::Result<()> {
+    self
+      .grid
+      .clear_cells(self.grid.row, self.grid.col, Style::default());
+    Ok(())
+  }
+
+  fn clear_screen_below(&mut self) -> io::Result<()> {
+    self.clearline()?;
+    for row in self.grid.row + 1..self.grid.height {
+      self.grid.clear_cells(row, 0, Style::default());
+    }
+    Ok(())
+  }
+
+  fn newline(&mut self) -> io::Result<()> {
+    self.clearline()?;
+    self.grid.col = 0;
+    self.grid.line_feed();
+    Ok(())
+  }
+
+  fn set_col(&mut self, col: usize) -> io::Result<()> {
+    self.grid.col = std::cmp::min(col, self.grid.width.saturating_sub(1));
+    Ok(())
+  }
+
+  fn move_up(&mut self, row_count: usize) -> io::Result<()> {
+    self.grid.row = self.grid.row.saturating_sub(row_count);
+    Ok(())
+  }
+
+  fn move_down(&mut self, row_count: usize) -> io::Result<()> {
+    self.grid.row = std::cmp::min(
+      self.grid.row + row_count,
+      self.grid.height.saturating_sub(1),
+    );
+    Ok(())
+  }
+
+  fn set_no_wrap(&mut self) -> io::Result<()> {
+    self.grid.wrap = false;
+    Ok(())
+  }
+
+  fn set_wrap(&mut self) -> io::Result<()> {
+    self.grid.wrap = true;
+    Ok(())
+  }
+
+  fn set_fg(&mut self, _color: Color) -> io::Result<()> {
+    Ok(())
+  }
+
+  fn set_bg(&mut self, _color: Color) -> io::Result<()> {
+    Ok(())
+  }
+
+  fn set_bold(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+
+  fn set_underline(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+
+  fn set_invert(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+
+  fn set_normal(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
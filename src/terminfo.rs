@@ -0,0 +1,530 @@
+use crate::other_error;
+use std::{
+  env, fs,
+  io::{self, Write},
+  path::{Path, PathBuf},
+};
+
+// The capabilities of a terminal read from the compiled entry for it in the terminfo database.
+// Only the standard capabilities are read, the extended ones that follow them are ignored.
+pub(crate) struct TermInfo {
+  numbers: Vec<i32>,
+  strings: Vec<Option<Vec<u8>>>,
+}
+
+// the position of a capability in the standard order used by the compiled format
+#[derive(Clone, Copy)]
+pub(crate) struct NumberCap(usize);
+#[derive(Clone, Copy)]
+pub(crate) struct StringCap(usize);
+
+pub(crate) const MAX_COLORS: NumberCap = NumberCap(13);
+
+pub(crate) const CLR_EOL: StringCap = StringCap(6);
+pub(crate) const CLR_EOS: StringCap = StringCap(7);
+pub(crate) const COLUMN_ADDRESS: StringCap = StringCap(8);
+pub(crate) const ENTER_BOLD_MODE: StringCap = StringCap(27);
+pub(crate) const ENTER_REVERSE_MODE: StringCap = StringCap(34);
+pub(crate) const ENTER_UNDERLINE_MODE: StringCap = StringCap(36);
+pub(crate) const EXIT_ATTRIBUTE_MODE: StringCap = StringCap(39);
+pub(crate) const KEY_BACKSPACE: StringCap = StringCap(55);
+pub(crate) const KEY_DOWN: StringCap = StringCap(61);
+pub(crate) const KEY_UP: StringCap = StringCap(87);
+pub(crate) const KEYPAD_LOCAL: StringCap = StringCap(88);
+pub(crate) const KEYPAD_XMIT: StringCap = StringCap(89);
+pub(crate) const PARM_DOWN_CURSOR: StringCap = StringCap(107);
+pub(crate) const PARM_UP_CURSOR: StringCap = StringCap(114);
+pub(crate) const ENTER_AM_MODE: StringCap = StringCap(151);
+pub(crate) const EXIT_AM_MODE: StringCap = StringCap(152);
+pub(crate) const SET_A_FOREGROUND: StringCap = StringCap(359);
+pub(crate) const SET_A_BACKGROUND: StringCap = StringCap(360);
+
+// numbers are 16 bit in the original format and 32 bit in the one ncurses uses for larger values
+const MAGIC: i16 = 0o432;
+const MAGIC_32_BIT: i16 = 0o1036;
+
+impl TermInfo {
+  // The entry for $TERM, None when it isn't set or there is no valid entry for it
+  pub fn from_env() -> Option<TermInfo> {
+    let term = env::var("TERM").ok().filter(|term| !term.is_empty())?;
+    TermInfo::load(&term).ok()
+  }
+
+  // Load the entry for the terminal from the first directory of the database that has one
+  pub fn load(term: &str) -> io::Result<TermInfo> {
+    let first_byte = match term.bytes().next() {
+      Some(byte) if !term.contains('/') => byte,
+      _ => return other_error!(format!("Invalid terminal name '{}'", term)),
+    };
+
+    for dir in search_dirs() {
+      // entries are in a directory named after their first character, or its hex code on
+      // filesystems that are case insensitive
+      let paths = [
+        dir.join((first_byte as char).to_string()).join(term),
+        dir.join(format!("{:x}", first_byte)).join(term),
+      ];
+      for path in &paths {
+        match fs::read(path) {
+          Ok(data) => return TermInfo::parse(&data),
+          Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+          Err(error) => return Err(error),
+        }
+      }
+    }
+    other_error!(format!("No terminfo entry for '{}'", term))
+  }
+
+  pub fn parse(data: &[u8]) -> io::Result<TermInfo> {
+    let invalid = || other_error!("Invalid terminfo entry");
+    let read_i16 = |offset: usize| {
+      data
+        .get(offset..offset + 2)
+        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+    let read_i32 = |offset: usize| {
+      data
+        .get(offset..offset + 4)
+        .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    let mut header = [0; 6];
+    for (idx, value) in header.iter_mut().enumerate() {
+      match read_i16(idx * 2) {
+        Some(read) => *value = read,
+        None => return invalid(),
+      }
+    }
+    let [magic, names_size, bools_count, numbers_count, strings_count, table_size] = header;
+    let number_size = match magic {
+      MAGIC => 2,
+      MAGIC_32_BIT => 4,
+      _ => return invalid(),
+    };
+    if [
+      names_size,
+      bools_count,
+      numbers_count,
+      strings_count,
+      table_size,
+    ]
+    .iter()
+    .any(|&size| size < 0)
+    {
+      return invalid();
+    }
+
+    // the numbers start on an even offset
+    let mut offset = 12 + names_size as usize + bools_count as usize;
+    offset += offset % 2;
+
+    let mut numbers = Vec::with_capacity(numbers_count as usize);
+    for _ in 0..numbers_count {
+      let number = if number_size == 2 {
+        read_i16(offset).map(i32::from)
+      } else {
+        read_i32(offset)
+      };
+      match number {
+        Some(number) => numbers.push(number),
+        None => return invalid(),
+      }
+      offset += number_size;
+    }
+
+    let table_start = offset + strings_count as usize * 2;
+    let table = match data.get(table_start..table_start + table_size as usize) {
+      Some(table) => table,
+      None => return invalid(),
+    };
+    let mut strings = Vec::with_capacity(strings_count as usize);
+    for _ in 0..strings_count {
+      let string_offset = match read_i16(offset) {
+        Some(string_offset) => string_offset,
+        None => return invalid(),
+      };
+      offset += 2;
+
+      // negative offsets are capabilities that are absent or cancelled
+      if string_offset < 0 {
+        strings.push(None);
+        continue;
+      }
+      let string = match table.get(string_offset as usize..) {
+        Some(rest) => rest.split(|&byte| byte == 0).next().unwrap_or(rest),
+        None => return invalid(),
+      };
+      strings.push(Some(strip_delays(string)));
+    }
+
+    Ok(TermInfo { numbers, strings })
+  }
+
+  // None when the capability is absent
+  pub fn number(&self, cap: NumberCap) -> Option<i32> {
+    self
+      .numbers
+      .get(cap.0)
+      .copied()
+      .filter(|&number| number >= 0)
+  }
+
+  pub fn string(&self, cap: StringCap) -> Option<&[u8]> {
+    self.strings.get(cap.0)?.as_deref()
+  }
+}
+
+// The directories of the terminfo database in the order that ncurses searches them
+fn search_dirs() -> Vec<PathBuf> {
+  let mut dirs = Vec::new();
+  if let Some(dir) = env::var_os("TERMINFO") {
+    dirs.push(PathBuf::from(dir));
+  }
+  if let Some(home) = env::var_os("HOME") {
+    dirs.push(Path::new(&home).join(".terminfo"));
+  }
+  let default_dirs = ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"];
+  match env::var("TERMINFO_DIRS") {
+    Ok(dirs_var) => {
+      // an empty entry stands for the default directories
+      for dir in dirs_var.split(':') {
+        if dir.is_empty() {
+          dirs.extend(default_dirs.iter().map(PathBuf::from));
+        } else {
+          dirs.push(PathBuf::from(dir));
+        }
+      }
+    }
+    Err(_) => dirs.extend(default_dirs.iter().map(PathBuf::from)),
+  }
+  dirs
+}
+
+// Remove the padding like "$<5>" that slow terminals needed after some sequences
+fn strip_delays(string: &[u8]) -> Vec<u8> {
+  let mut stripped = Vec::with_capacity(string.len());
+  let mut rest = string;
+  while let Some((&byte, tail)) = rest.split_first() {
+    if byte == b'$' && tail.first() == Some(&b'<') {
+      if let Some(end) = tail.iter().position(|&byte| byte == b'>') {
+        rest = &tail[end + 1..];
+        continue;
+      }
+    }
+    stripped.push(byte);
+    rest = tail;
+  }
+  stripped
+}
+
+// The most values the stack of expand holds, like in ncurses pushing onto a full stack does nothing
+const STACK_SIZE: usize = 20;
+
+// The stack of expand, popping from an empty stack gives 0
+struct Stack {
+  values: [i32; STACK_SIZE],
+  len: usize,
+}
+
+impl Stack {
+  fn push(&mut self, value: i32) {
+    if self.len < STACK_SIZE {
+      self.values[self.len] = value;
+      self.len += 1;
+    }
+  }
+
+  fn pop(&mut self) -> i32 {
+    if self.len == 0 {
+      return 0;
+    }
+    self.len -= 1;
+    self.values[self.len]
+  }
+}
+
+// Substitute the parameters into a parameterized capability like "\x1b[%i%p1%dG", appending the
+// result to the output. This is the stack machine of tparm, string parameters aren't supported
+// since none of the capabilities used take them. Nothing is allocated apart from growing the
+// output so that it can be reused for each sequence.
+pub(crate) fn expand(cap: &[u8], params: &[i32], output: &mut Vec<u8>) {
+  let mut param_values = [0; 9];
+  let count = params.len().min(param_values.len());
+  param_values[..count].copy_from_slice(&params[..count]);
+  let mut params = param_values;
+  let mut stack = Stack {
+    values: [0; STACK_SIZE],
+    len: 0,
+  };
+  let mut variables = [0; 52];
+  let mut i = 0;
+  while i < cap.len() {
+    let byte = cap[i];
+    i += 1;
+    if byte != b'%' {
+      output.push(byte);
+      continue;
+    }
+
+    let op = match cap.get(i) {
+      Some(&op) => op,
+      None => break,
+    };
+    i += 1;
+    match op {
+      b'%' => output.push(b'%'),
+      b'c' => output.push(stack.pop() as u8),
+      b'p' => {
+        let idx = cap
+          .get(i)
+          .map_or(0, |digit| digit.wrapping_sub(b'1') as usize);
+        i += 1;
+        stack.push(params.get(idx).copied().unwrap_or(0));
+      }
+      b'P' | b'g' => {
+        let idx = match cap.get(i) {
+          Some(name @ b'a'..=b'z') => Some((name - b'a') as usize),
+          Some(name @ b'A'..=b'Z') => Some((name - b'A') as usize + 26),
+          _ => None,
+        };
+        i += 1;
+        if let Some(idx) = idx {
+          if op == b'P' {
+            variables[idx] = stack.pop();
+          } else {
+            stack.push(variables[idx]);
+          }
+        }
+      }
+      b'\'' => {
+        stack.push(cap.get(i).copied().map_or(0, i32::from));
+        i += 2;
+      }
+      b'{' => {
+        let end = cap[i..]
+          .iter()
+          .position(|&byte| byte == b'}')
+          .map_or(cap.len(), |end| i + end);
+        let number = std::str::from_utf8(&cap[i..end])
+          .ok()
+          .and_then(|number| number.parse().ok())
+          .unwrap_or(0);
+        stack.push(number);
+        i = end + 1;
+      }
+      b'i' => {
+        params[0] += 1;
+        params[1] += 1;
+      }
+      b'!' => {
+        let value = stack.pop();
+        stack.push((value == 0) as i32);
+      }
+      b'~' => {
+        let value = stack.pop();
+        stack.push(!value);
+      }
+      b'+' | b'-' | b'*' | b'/' | b'm' | b'&' | b'|' | b'^' | b'=' | b'<' | b'>' | b'A' | b'O' => {
+        let b = stack.pop();
+        let a = stack.pop();
+        stack.push(match op {
+          b'+' => a.wrapping_add(b),
+          b'-' => a.wrapping_sub(b),
+          b'*' => a.wrapping_mul(b),
+          b'/' => a.checked_div(b).unwrap_or(0),
+          b'm' => a.checked_rem(b).unwrap_or(0),
+          b'&' => a & b,
+          b'|' => a | b,
+          b'^' => a ^ b,
+          b'=' => (a == b) as i32,
+          b'<' => (a < b) as i32,
+          b'>' => (a > b) as i32,
+          b'A' => (a != 0 && b != 0) as i32,
+          _ => (a != 0 || b != 0) as i32,
+        });
+      }
+      b'?' | b';' => {}
+      b't' => {
+        if stack.pop() == 0 {
+          i = skip_branch(cap, i, true);
+        }
+      }
+      // the end of the branch that was taken
+      b'e' => i = skip_branch(cap, i, false),
+      _ => {
+        i = format_number(output, cap, i - 1, stack.pop());
+      }
+    }
+  }
+}
+
+// The position after the %e or %; ending the branch of a conditional that starts at i, skipping
+// over any conditionals nested inside it. Only %; ends the branch when else_ends is false.
+fn skip_branch(cap: &[u8], mut i: usize, else_ends: bool) -> usize {
+  let mut depth = 0;
+  while i + 1 < cap.len() {
+    if cap[i] != b'%' {
+      i += 1;
+      continue;
+    }
+    match cap[i + 1] {
+      b'?' => depth += 1,
+      b';' if depth == 0 => return i + 2,
+      b';' => depth -= 1,
+      b'e' if depth == 0 && else_ends => return i + 2,
+      _ => {}
+    }
+    i += 2;
+  }
+  cap.len()
+}
+
+// Format the number with a printf style conversion like "%d" or "%:-3x" that starts at i after
+// the '%', appending the text to the output and returning the position after the conversion
+fn format_number(output: &mut Vec<u8>, cap: &[u8], mut i: usize, number: i32) -> usize {
+  if cap.get(i) == Some(&b':') {
+    i += 1;
+  }
+  let mut left_align = false;
+  let mut sign = false;
+  while let Some(&flag) = cap.get(i) {
+    match flag {
+      b'-' => left_align = true,
+      b'+' | b' ' => sign = true,
+      b'#' => {}
+      _ => break,
+    }
+    i += 1;
+  }
+  let zero_pad = cap.get(i) == Some(&b'0');
+  let read_number = |i: &mut usize| {
+    let start = *i;
+    while cap.get(*i).is_some_and(u8::is_ascii_digit) {
+      *i += 1;
+    }
+    std::str::from_utf8(&cap[start..*i])
+      .ok()
+      .and_then(|digits| digits.parse::<usize>().ok())
+  };
+  let width = read_number(&mut i).unwrap_or(0);
+  let precision = if cap.get(i) == Some(&b'.') {
+    i += 1;
+    read_number(&mut i)
+  } else {
+    None
+  };
+
+  // the digits are written straight to the output then padded where they are
+  let start = output.len();
+  // writing to a Vec can't fail
+  let _ = match cap.get(i) {
+    Some(b'o') => write!(output, "{:o}", number),
+    Some(b'x') => write!(output, "{:x}", number),
+    Some(b'X') => write!(output, "{:X}", number),
+    Some(b'd') | Some(b's') => write!(output, "{}", number.unsigned_abs()),
+    // an unknown conversion is written as it is
+    _ => {
+      output.extend_from_slice(&cap[i.min(cap.len())..]);
+      return cap.len();
+    }
+  };
+  let insert = |output: &mut Vec<u8>, byte: u8, count: usize| {
+    output.resize(output.len() + count, byte);
+    output[start..].rotate_right(count);
+  };
+  if let Some(precision) = precision {
+    insert(output, b'0', precision.saturating_sub(output.len() - start));
+  }
+  if number < 0 && matches!(cap[i], b'd' | b's') {
+    insert(output, b'-', 1);
+  } else if sign && matches!(cap[i], b'd' | b's') {
+    insert(output, b'+', 1);
+  }
+
+  let padding = width.saturating_sub(output.len() - start);
+  if left_align {
+    output.resize(output.len() + padding, b' ');
+  } else if zero_pad {
+    insert(output, b'0', padding);
+  } else {
+    insert(output, b' ', padding);
+  }
+  i + 1
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const XTERM_SETAF: &[u8] = b"\x1b[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m";
+
+  // a compiled entry with the names "test|a test", the numbers cols#80 and colors#256 and the
+  // strings el and hpa
+  fn compiled_entry(magic: i16) -> Vec<u8> {
+    let names = b"test|a test\0";
+    let number_size = if magic == MAGIC { 2 } else { 4 };
+    let table = b"\x1b[K$<3>\0\x1b[%i%p1%dG\0";
+    let mut data = Vec::new();
+    for value in &[magic, names.len() as i16, 1, 14, 9, table.len() as i16] {
+      data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(names);
+    // one boolean then a byte to align the numbers
+    data.extend_from_slice(&[1, 0]);
+    for idx in 0..14 {
+      let number: i32 = match idx {
+        0 => 80,
+        13 => 256,
+        _ => -1,
+      };
+      data.extend_from_slice(&number.to_le_bytes()[..number_size]);
+    }
+    for offset in &[-1i16, -1, -1, -1, -1, -1, 0, -2, 8] {
+      data.extend_from_slice(&offset.to_le_bytes());
+    }
+    data.extend_from_slice(table);
+    data
+  }
+
+  #[test]
+  fn parses_compiled_entries() {
+    for &magic in &[MAGIC, MAGIC_32_BIT] {
+      let info = TermInfo::parse(&compiled_entry(magic)).unwrap();
+      assert_eq!(info.number(MAX_COLORS), Some(256));
+      assert_eq!(info.number(NumberCap(1)), None);
+      assert_eq!(info.string(CLR_EOL), Some(&b"\x1b[K"[..]));
+      assert_eq!(info.string(CLR_EOS), None);
+      assert_eq!(info.string(COLUMN_ADDRESS), Some(&b"\x1b[%i%p1%dG"[..]));
+      assert_eq!(info.string(SET_A_FOREGROUND), None);
+    }
+
+    let mut truncated = compiled_entry(MAGIC);
+    truncated.truncate(truncated.len() - 4);
+    assert!(TermInfo::parse(&truncated).is_err());
+  }
+
+  fn expand(cap: &[u8], params: &[i32]) -> Vec<u8> {
+    let mut output = Vec::new();
+    super::expand(cap, params, &mut output);
+    output
+  }
+
+  #[test]
+  fn expands_parameters() {
+    assert_eq!(expand(b"\x1b[%i%p1%dG", &[4]), b"\x1b[5G");
+    assert_eq!(expand(XTERM_SETAF, &[3]), b"\x1b[33m");
+    assert_eq!(expand(XTERM_SETAF, &[9]), b"\x1b[91m");
+    assert_eq!(expand(XTERM_SETAF, &[236]), b"\x1b[38;5;236m");
+    assert_eq!(
+      expand(b"%p1%c%p2%3d|%p2%:-3x|%p1%03o%%", &[65, 26]),
+      b"A 26|1a |101%"
+    );
+    assert_eq!(expand(b"%p1%Pa%ga%ga%*%d %'x'%c", &[7]), b"49 x");
+    assert_eq!(expand(b"%p1%5.3d|%p2%:+d", &[-7, 2]), b" -007|+2");
+
+    // the output is appended to so that a buffer can be reused
+    let mut output = b"x".to_vec();
+    super::expand(b"%p1%02d", &[5], &mut output);
+    assert_eq!(output, b"x05");
+  }
+}
@@ -1,234 +1,337 @@
-use crate::{def_c_str, other_error};
+use crate::{
+  color::Color,
+  escapes::{Capabilities, EscapeWriter},
+  other_error,
+  terminal::{Event, Terminal},
+  terminfo::{self, TermInfo},
+};
 use libc::{
-  c_int, c_void, close, fclose, fd_set, fflush, fileno, fprintf, fputc, ioctl, pselect, read,
-  setvbuf, sigemptyset, sighandler_t, signal, sigset_t, winsize, EINTR, FD_ISSET, FD_SET, FD_ZERO,
-  SIGWINCH, TIOCGWINSZ, _IOFBF,
+  c_int, c_long, fd_set, ioctl, pselect, sigemptyset, sighandler_t, signal, sigset_t, time_t,
+  timespec, winsize, EINTR, FD_ISSET, FD_SET, FD_ZERO, SIGWINCH, SIG_ERR, TIOCGWINSZ,
+};
+use std::{
+  fs::{File, OpenOptions},
+  io::{self, BufWriter, Read},
+  os::unix::io::AsRawFd,
+  time::Duration,
 };
-use std::{ffi::CString, io, io::Error};
 use termios::{tcsetattr, Termios, ECHO, ICANON, ICRNL, ISIG, TCSANOW};
 
-// Make unsafe call and turn non-zero exit statuses into an io error with the given string when
-// needed
-macro_rules! fwd_error_code {
-  ($expr: expr, $msg: expr) => {
-    if unsafe { $expr != 0 } {
-      return other_error!($msg);
-    }
-  };
-}
-
-macro_rules! terminal_printf {
-  ($self: expr, $($expr: expr),+) => {
-    if unsafe { fprintf($self.fout, $($expr),+) < 0 } {
-      return other_error!("Error printing to console")
-    }
-  }
-}
+// large enough to hold a full redraw of most windows so that it reaches the terminal at once
+const OUTPUT_BUFFER_SIZE: usize = 16 * 1024;
+// pasted text up to this size is read as a single key
+const INPUT_BUFFER_SIZE: usize = 1024;
 
-def_c_str! {
-  WRITE_FORMAT = "w";
-  CLEAR_LINE_FORMAT = "\x1b[K";
-  SGR_FORMAT = "\x1b[%im";
-  NEWLINE_FORMAT = "\x1b[K\n";
-  SET_COL_FORMAT = "\x1b[%iG";
-  NO_WRAP_FORMAT = "\x1b[?7l";
-  MOVE_UP_FORMAT = "\x1b[%iA";
-  WRAP_FORMAT = "\x1b[?7h";
+macro_rules! uninit_mem {
+  () => {
+    unsafe { std::mem::MaybeUninit::zeroed().assume_init() }
+  };
 }
 
 pub(crate) struct Tty {
-  fdin: i32,
-  fout: *mut libc::FILE,
+  fin: File,
+  // input that has been read but not returned as keys yet
+  input: Vec<u8>,
+  // the codes the terminfo entry gives for keys and the keys they are returned as
+  key_codes: Vec<(Vec<u8>, &'static str)>,
+  output: EscapeWriter<BufWriter<File>>,
   original_termios: Termios,
-  fg_color: i32,
-  pub max_width: u16,
-  pub max_height: u16,
+  max_width: u16,
+  max_height: u16,
 }
 
 extern "C" fn winch_handler(_: c_int) {}
 
 impl Tty {
   pub fn new(tty_path: &str) -> io::Result<Tty> {
-    unsafe { signal(SIGWINCH, winch_handler as sighandler_t) };
+    if unsafe { signal(SIGWINCH, winch_handler as *const () as sighandler_t) } == SIG_ERR {
+      return Err(io::Error::last_os_error());
+    }
 
-    let tty_filename_c = CString::new(tty_path)?;
-    let fdin = unsafe { libc::open(tty_filename_c.as_ptr(), libc::O_RDONLY) };
-    let fout = unsafe { libc::fopen(tty_filename_c.as_ptr(), WRITE_FORMAT.as_ptr()) };
+    let fin = File::open(tty_path)?;
+    let fout = OpenOptions::new().write(true).open(tty_path)?;
 
-    let original_termios = Termios::from_fd(fdin)?;
+    let original_termios = Termios::from_fd(fin.as_raw_fd())?;
 
-    let mut termios_copy = original_termios.clone();
+    let mut termios_copy = original_termios;
     termios_copy.c_iflag &= !(ICRNL);
     termios_copy.c_lflag &= !(ICANON | ECHO | ISIG);
-    tcsetattr(fdin, TCSANOW, &termios_copy)?;
-
-    fwd_error_code!(
-      setvbuf(fout, std::ptr::null_mut(), _IOFBF, 4096),
-      "Could not setvbuf"
-    );
-
-    let mut ws = winsize {
-      ws_row: 0,
-      ws_col: 0,
-      ws_xpixel: 0,
-      ws_ypixel: 0,
+    tcsetattr(fin.as_raw_fd(), TCSANOW, &termios_copy)?;
+
+    let info = TermInfo::from_env();
+    let key_codes = match &info {
+      Some(info) => [
+        (terminfo::KEY_UP, KEY_UP),
+        (terminfo::KEY_DOWN, KEY_DOWN),
+        (terminfo::KEY_BACKSPACE, "\x7f"),
+      ]
+      .iter()
+      .filter_map(|&(cap, key)| Some((info.string(cap)?.to_vec(), key)))
+      .filter(|(code, key)| !code.is_empty() && code != key.as_bytes())
+      .collect(),
+      None => Vec::new(),
     };
-    fwd_error_code!(
-      ioctl(fileno(fout), TIOCGWINSZ, &mut ws),
-      "Could not get window size"
+    let output = EscapeWriter::with_capabilities(
+      BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, fout),
+      Capabilities::from_env(info.as_ref()),
     );
 
-    Ok(Tty {
-      fdin,
-      fout,
-      fg_color: 9,
+    let mut tty = Tty {
+      fin,
+      input: Vec::new(),
+      key_codes,
+      output,
       original_termios,
-      max_width: ws.ws_col,
-      max_height: ws.ws_row,
-    })
+      max_width: 0,
+      max_height: 0,
+    };
+    tty.output.enter_keypad_mode()?;
+    tty.update_size()?;
+    Ok(tty)
   }
 
-  pub fn sgr(&self, code: i32) -> io::Result<()> {
-    terminal_printf!(self, SGR_FORMAT.as_ptr(), code);
-    Ok(())
+  pub fn reset(&mut self) {
+    // it isn't the best if we can't reset the terminal but at least don't mess with
+    // the output of the matches
+    let _ = self.output.exit_keypad_mode();
+    let _ = self.output.flush();
+    let _ = tcsetattr(self.fin.as_raw_fd(), TCSANOW, &self.original_termios);
   }
 
-  pub fn set_fg(&mut self, color: i32) -> io::Result<()> {
-    if self.fg_color != color {
-      self.sgr(30 + color)?;
-      self.fg_color = color;
+  // the next key from the input that has been read but not yet returned
+  fn next_key(&mut self) -> Option<String> {
+    // keys with codes that differ from the ones the selector binds are translated
+    for (code, key) in &self.key_codes {
+      if self.input.starts_with(code) {
+        self.input.drain(..code.len());
+        return Some(key.to_string());
+      }
     }
-    Ok(())
+
+    let len = key_len(&self.input)?;
+    let key = String::from_utf8_lossy(&self.input[..len]).into_owned();
+    self.input.drain(..len);
+    Some(key)
   }
 
-  pub fn set_invert(&self) -> io::Result<()> {
-    self.sgr(7)
+  // Wait for the next key or resize, returning None when the timeout passes first. No timeout
+  // waits for as long as it takes.
+  fn next_event(&mut self, timeout: Option<Duration>) -> io::Result<Option<Event>> {
+    if let Some(key) = self.next_key() {
+      return Ok(Some(Event::Key(key)));
+    }
+
+    let fdin = self.fin.as_raw_fd();
+    let timeout = timeout.map(|timeout| timespec {
+      tv_sec: timeout.as_secs() as time_t,
+      tv_nsec: timeout.subsec_nanos() as c_long,
+    });
+    let mut buffer = [0; INPUT_BUFFER_SIZE];
+
+    // pselect before the read so that the WINCH signal can interrupt
+    loop {
+      let mut fdset: fd_set = uninit_mem!();
+      unsafe {
+        FD_ZERO(&mut fdset);
+        FD_SET(fdin, &mut fdset);
+      }
+
+      let mut sig_mask: sigset_t = uninit_mem!();
+      unsafe {
+        sigemptyset(&mut sig_mask);
+      }
+
+      let err = unsafe {
+        pselect(
+          fdin + 1,
+          &mut fdset,
+          std::ptr::null_mut(),
+          std::ptr::null_mut(),
+          timeout
+            .as_ref()
+            .map_or(std::ptr::null(), |timeout| timeout as *const timespec),
+          &sig_mask,
+        )
+      };
+
+      if err < 0 {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(EINTR) {
+          return Ok(Some(Event::Resize));
+        }
+        return Err(error);
+      } else if err == 0 {
+        return Ok(None);
+      } else if unsafe { FD_ISSET(fdin, &mut fdset) } {
+        let len = self.fin.read(&mut buffer)?;
+        if len == 0 {
+          return other_error!("The terminal was closed");
+        }
+        self.input.extend_from_slice(&buffer[..len]);
+        if let Some(key) = self.next_key() {
+          return Ok(Some(Event::Key(key)));
+        }
+      }
+    }
   }
+}
 
-  pub fn set_no_wrap(&self) -> io::Result<()> {
-    terminal_printf!(self, NO_WRAP_FORMAT.as_ptr());
-    Ok(())
+// Length of the key at the start of the input, which is an escape sequence, a control character or
+// a run of text. None when the input is empty or ends part way through a character or an escape
+// sequence.
+fn key_len(input: &[u8]) -> Option<usize> {
+  let is_control = |byte: u8| byte < 0x20 || byte == 0x7f;
+  // number of bytes in the UTF-8 encoding of the character starting with the byte
+  let char_len = |byte: u8| match byte.leading_ones() {
+    2 => 2,
+    3 => 3,
+    4 => 4,
+    _ => 1,
+  };
+
+  match input {
+    [] => None,
+    [b'\x1b', b'[', rest @ ..] => {
+      // wait for the final byte when the parameters run to the end of the read, a sequence that is
+      // broken off by any other byte ends before it
+      let end = rest.iter().position(|byte| !(0x20..=0x3f).contains(byte))?;
+      if (0x40..=0x7e).contains(&rest[end]) {
+        Some(end + 3)
+      } else {
+        Some(end + 2)
+      }
+    }
+    [b'\x1b', b'O'] => None,
+    [b'\x1b', b'O', _, ..] => Some(3),
+    // a character pressed with alt
+    [b'\x1b', byte, ..] if !is_control(*byte) => {
+      Some(1 + char_len(*byte)).filter(|&len| len <= input.len())
+    }
+    [byte, ..] if is_control(*byte) => Some(1),
+    _ => {
+      let end = input
+        .iter()
+        .position(|&byte| is_control(byte))
+        .unwrap_or(input.len());
+      match std::str::from_utf8(&input[..end]) {
+        // wait for the rest of a character that was cut off by the end of the read
+        Err(error) if error.error_len().is_none() && end == input.len() => {
+          Some(error.valid_up_to()).filter(|&len| len > 0)
+        }
+        _ => Some(end),
+      }
+    }
   }
+}
 
-  pub fn set_wrap(&self) -> io::Result<()> {
-    terminal_printf!(self, WRAP_FORMAT.as_ptr());
-    Ok(())
+impl Terminal for Tty {
+  fn width(&self) -> usize {
+    self.max_width as usize
   }
 
-  // pub fn set_underline(&self) -> io::Result<()> { self.sgr(4) }
+  fn height(&self) -> usize {
+    self.max_height as usize
+  }
 
-  pub fn set_normal(&mut self) -> io::Result<()> {
-    self.sgr(0)?;
-    self.fg_color = 9;
+  // Query the current dimensions of the terminal, this must be called again after a WINCH signal
+  fn update_size(&mut self) -> io::Result<()> {
+    let mut ws = winsize {
+      ws_row: 0,
+      ws_col: 0,
+      ws_xpixel: 0,
+      ws_ypixel: 0,
+    };
+    if unsafe { ioctl(self.fin.as_raw_fd(), TIOCGWINSZ, &mut ws) } != 0 {
+      return Err(io::Error::last_os_error());
+    }
+
+    self.max_width = ws.ws_col;
+    self.max_height = ws.ws_row;
     Ok(())
   }
 
-  pub fn move_up(&self, row_count: i32) -> io::Result<()> {
-    terminal_printf!(self, MOVE_UP_FORMAT.as_ptr(), row_count);
-    Ok(())
+  fn read_event(&mut self) -> io::Result<Event> {
+    loop {
+      if let Some(event) = self.next_event(None)? {
+        return Ok(event);
+      }
+    }
   }
 
-  /*
-  pub fn putc(&self, c: i32) {
-    unsafe { fputc(c, self.fout) };
+  fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+    self.next_event(Some(timeout))
   }
-  */
 
-  pub fn print(&self, string: &str) -> io::Result<()> {
-    terminal_printf!(self, CString::new(string)?.as_ptr());
-    Ok(())
+  fn print(&mut self, string: &str) -> io::Result<()> {
+    self.output.print(string)
   }
 
-  // Remove everything after cursor
-  pub fn clearline(&self) -> io::Result<()> {
-    terminal_printf!(self, CLEAR_LINE_FORMAT.as_ptr());
-    Ok(())
+  fn clearline(&mut self) -> io::Result<()> {
+    self.output.clearline()
   }
 
-  // Remove everything after cursor then move to next line
-  pub fn newline(&self) -> io::Result<()> {
-    terminal_printf!(self, NEWLINE_FORMAT.as_ptr());
-    Ok(())
+  fn clear_screen_below(&mut self) -> io::Result<()> {
+    self.output.clear_screen_below()
   }
 
-  pub fn flush(&self) {
-    unsafe { fflush(self.fout) };
+  fn newline(&mut self) -> io::Result<()> {
+    self.output.newline()
   }
 
-  pub fn set_col(&self, col: i32) -> io::Result<()> {
-    terminal_printf!(self, SET_COL_FORMAT.as_ptr(), col + 1);
-    Ok(())
+  fn set_col(&mut self, col: usize) -> io::Result<()> {
+    self.output.set_col(col)
   }
 
-  pub fn reset(&mut self) {
-    unsafe {
-      fclose(self.fout);
-    }
-    // it isn't the best if we can't reset the terminal but at least don't mess with
-    // the output of the matches
-    if tcsetattr(self.fdin, TCSANOW, &self.original_termios).is_ok() {
-      unsafe {
-        close(self.fdin);
-      }
-    }
+  fn move_up(&mut self, row_count: usize) -> io::Result<()> {
+    self.output.move_up(row_count)
   }
 
-  pub fn get_reader(&self) -> TtyReader {
-    TtyReader { fdin: self.fdin }
+  fn move_down(&mut self, row_count: usize) -> io::Result<()> {
+    self.output.move_down(row_count)
   }
-}
 
-pub(crate) struct TtyReader {
-  fdin: i32,
-}
+  fn set_no_wrap(&mut self) -> io::Result<()> {
+    self.output.set_no_wrap()
+  }
 
-macro_rules! uninit_mem {
-  () => {
-    unsafe { std::mem::MaybeUninit::zeroed().assume_init() }
-  };
-}
+  fn set_wrap(&mut self) -> io::Result<()> {
+    self.output.set_wrap()
+  }
 
-impl TtyReader {
-  pub fn read(&self) -> io::Result<[u8; 5]> {
-    let mut input: [u8; 5] = [0; 5];
+  fn set_fg(&mut self, color: Color) -> io::Result<()> {
+    self.output.set_fg(color)
+  }
 
-    // pselect before the read so that the WINCH signal can interrupt
-    loop {
-      let mut fdset: fd_set = uninit_mem!();
-      unsafe {
-        FD_ZERO(&mut fdset);
-        FD_SET(self.fdin, &mut fdset);
-      }
+  fn set_bg(&mut self, color: Color) -> io::Result<()> {
+    self.output.set_bg(color)
+  }
 
-      let mut sig_mask: sigset_t = uninit_mem!();
-      unsafe {
-        sigemptyset(&mut sig_mask);
-      }
+  fn set_bold(&mut self) -> io::Result<()> {
+    self.output.set_bold()
+  }
 
-      let err = unsafe {
-        pselect(
-          self.fdin + 1,
-          &mut fdset,
-          std::ptr::null_mut(),
-          std::ptr::null_mut(),
-          std::ptr::null_mut(),
-          &sig_mask,
-        )
-      };
+  fn set_underline(&mut self) -> io::Result<()> {
+    self.output.set_underline()
+  }
 
-      if err < 0 {
-        if Error::last_os_error().raw_os_error() == Some(EINTR) {
-          return Ok(input);
-        } else {
-          return other_error!("Could not read from terminal");
-        }
-      } else if unsafe { FD_ISSET(self.fdin, &mut fdset) } {
-        break;
-      }
-    }
+  fn set_invert(&mut self) -> io::Result<()> {
+    self.output.set_invert()
+  }
 
-    unsafe { read(self.fdin, input.as_mut_ptr() as *mut c_void, 4) };
-    Ok(input)
+  fn set_normal(&mut self) -> io::Result<()> {
+    self.output.set_normal()
+  }
+
+  fn begin_synchronized_update(&mut self) -> io::Result<()> {
+    self.output.begin_synchronized_update()
+  }
+
+  fn end_synchronized_update(&mut self) -> io::Result<()> {
+    self.output.end_synchronized_update()
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.output.flush()
   }
 }
 
@@ -241,7 +344,7 @@ pub(crate) fn find_last_sgr_byte(bytes: &[u8]) -> usize {
   let len = bytes.len();
 
   loop {
-    if i > len - 4 {
+    if i + 4 > len {
       return last_sgr;
     }
 
@@ -293,9 +396,67 @@ def_keys! {
   KEY_DOWN_ALTERNATE => "\x1bOB";
 }
 
-#[macro_export]
 macro_rules! control_key {
   ($char: expr) => {
     String::from_utf8_lossy(&[($char.to_ascii_uppercase() - b'@')])
   };
 }
+pub(crate) use control_key;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // split the input into keys the way that it is read from the terminal
+  fn keys(input: &[u8]) -> Vec<&[u8]> {
+    let mut keys = Vec::new();
+    let mut rest = input;
+    while let Some(len) = key_len(rest) {
+      keys.push(&rest[..len]);
+      rest = &rest[len..];
+    }
+    assert!(rest.len() < 4, "input was left over: {:?}", rest);
+    keys
+  }
+
+  #[test]
+  fn splits_input_into_keys() {
+    assert_eq!(
+      keys(b"ab\x1b[A\x1bOBcd\r\x7f\x1bx\x1b"),
+      [
+        &b"ab"[..],
+        b"\x1b[A",
+        b"\x1bOB",
+        b"cd",
+        b"\r",
+        b"\x7f",
+        b"\x1bx",
+        b"\x1b",
+      ]
+    );
+    assert_eq!(keys(b"\x1b[1;5C\t"), [&b"\x1b[1;5C"[..], b"\t"]);
+  }
+
+  #[test]
+  fn waits_for_the_rest_of_a_character() {
+    let input = "aé".as_bytes();
+    assert_eq!(key_len(&input[..2]), Some(1));
+    assert_eq!(key_len(&input[1..2]), None);
+    assert_eq!(key_len(input), Some(3));
+    // invalid bytes are passed on to be replaced
+    assert_eq!(key_len(b"\xffa\r"), Some(2));
+  }
+
+  #[test]
+  fn waits_for_the_rest_of_an_escape_sequence() {
+    // the input of two reads
+    let mut input = b"\x1b[".to_vec();
+    assert_eq!(key_len(&input), None);
+    input.push(b'B');
+    assert_eq!(key_len(&input), Some(3));
+
+    assert_eq!(key_len(b"\x1b[1;5"), None);
+    assert_eq!(key_len(b"\x1bO"), None);
+    assert_eq!(keys(b"\x1b[1\r"), [&b"\x1b[1"[..], b"\r"]);
+  }
+}